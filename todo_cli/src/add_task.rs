@@ -1,8 +1,24 @@
+use crate::history::History;
+use crate::priority::Priority;
+use crate::recurrence::Recurrence;
+use chrono::{DateTime, Utc};
 use crate::todo::Todo;
 
-pub fn add_task(todos: &mut Vec<Todo>, filtered_todos: &mut Vec<Todo>, title: String, content: String, priority: String, deadline: String) {
-    let new_todo = Todo::new(title, content, priority, deadline);
-    filtered_todos.push(new_todo);
-    *todos = filtered_todos.clone();
+#[allow(clippy::too_many_arguments)]
+pub fn add_task(
+    todos: &mut Vec<Todo>,
+    title: String,
+    content: String,
+    priority: Priority,
+    tags: Vec<String>,
+    deadline: Option<DateTime<Utc>>,
+    recurrence: Option<Recurrence>,
+    project: String,
+    history: &mut History,
+) {
+    let mut new_todo = Todo::new(title, content, priority, deadline);
+    new_todo.tags = tags;
+    new_todo.recurrence = recurrence;
+    new_todo.project = project;
+    history.insert(todos, &[todos.len()], new_todo, "Add");
 }
-
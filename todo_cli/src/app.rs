@@ -0,0 +1,125 @@
+use crate::config::config;
+use crate::handle_input::handle_input;
+use crate::history::{history_path, History};
+use crate::input::InputMode;
+use crate::priority::Priority;
+use crate::project::ListView;
+use crate::sort::SortMode;
+use crate::store::Store;
+use crate::terminal::signal_exit_code;
+use crate::todo::{StoreError, Todo};
+use crossterm::event::KeyEvent;
+use std::time::Instant;
+use tui::widgets::ListState;
+
+// App に届く出来事
+pub enum Event {
+    Key(KeyEvent),
+    // 入力待ちの合間。他の ltd が保存した内容を取り込む
+    Tick,
+    // 終了のシグナルを受け取った(シグナル番号)
+    Signal(usize),
+}
+
+// update の結果として端末の側でしてほしいこと
+#[derive(Debug, PartialEq)]
+pub enum Effect {
+    HideCursor,
+    ShowCursor,
+    Quit(i32),
+}
+
+// TUI の状態を全部まとめたもの。端末には触らないので、キーを順に流し込むだけで動かせる
+pub struct App {
+    // 全部のタスク。絞り込みは view で表示するときだけ行う
+    pub todos: Vec<Todo>,
+    pub state: ListState,
+    pub input_mode: InputMode,
+    pub input_title: String,
+    pub input_content: String,
+    pub input_priority: Priority,
+    pub input_deadline: String,
+    pub input_recurrence: String,
+    pub input_tags: String,
+    pub search_query: String,
+    pub search_state: ListState,
+    pub sort_mode: SortMode,
+    pub subtask_state: ListState,
+    pub view: ListView,
+    pub tag_state: ListState,
+    pub status_message: String,
+    pub load_error: Option<StoreError>,
+    pub store: Store,
+    pub history: History,
+    // スペースを2回続けて押すと検索になる。1回目を押した時刻
+    pub last_space_press: Option<Instant>,
+}
+
+impl App {
+    // 読み込みに失敗してもパニックせず、起動後に復旧画面を出す
+    pub fn new(mut store: Store) -> Self {
+        let (todos, load_error) = match store.load() {
+            Ok(todos) => (todos, None),
+            Err(e) => (vec![], Some(e)),
+        };
+        let persisted_history = config().persist_history.then(|| history_path(store.path()));
+        let mut state = ListState::default();
+        state.select(Some(0));
+        let mut search_state = ListState::default();
+        search_state.select(Some(0));
+        let mut view = ListView::default();
+        let mut sort_mode = SortMode::ByCompletion;
+        view.apply(store.projects(), &mut sort_mode);
+        App {
+            todos,
+            state,
+            input_mode: if load_error.is_some() {
+                InputMode::LoadFailed
+            } else {
+                InputMode::Normal
+            },
+            input_title: String::new(),
+            input_content: String::new(),
+            input_priority: Priority::default(),
+            input_deadline: String::new(),
+            input_recurrence: String::new(),
+            input_tags: String::new(),
+            search_query: String::new(),
+            search_state,
+            sort_mode,
+            subtask_state: ListState::default(),
+            view,
+            tag_state: ListState::default(),
+            status_message: String::new(),
+            load_error,
+            store,
+            history: History::new(config().history_limit, persisted_history),
+            last_space_press: None,
+        }
+    }
+
+    pub fn update(&mut self, event: Event) -> Vec<Effect> {
+        match event {
+            Event::Key(key) => handle_input(self, key),
+            Event::Tick => {
+                if self.input_mode == InputMode::Normal {
+                    if let Ok(true) = self.store.reload_if_changed(&mut self.todos) {
+                        self.history.clear();
+                        self.view.apply(self.store.projects(), &mut self.sort_mode);
+                        if self.state.selected().unwrap_or(0) >= self.todos.len() {
+                            self.state.select(Some(self.todos.len().saturating_sub(1)));
+                        }
+                    }
+                }
+                vec![]
+            }
+            // 読み込みに失敗したままのときは、壊れたファイルを上書きしない
+            Event::Signal(signal) => {
+                if self.load_error.is_none() {
+                    let _ = self.store.save(&mut self.todos);
+                }
+                vec![Effect::Quit(signal_exit_code(signal))]
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::project::ProjectSettings;
+use crate::store::{is_sqlite_path, SaveOutcome, Store};
+use crate::todo::{decode_todos, encode_todos, load_todos_from, write_atomic, StoreError, Todo};
+use chrono::{Local, NaiveDateTime};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// 保存のたびに残しておく世代数
+pub const MAX_BACKUPS: usize = 10;
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3f";
+
+pub struct Backup {
+    pub path: PathBuf,
+    pub created_at: NaiveDateTime,
+}
+
+// todos.json のバックアップは todos.json.backups/ に置く
+pub fn backup_dir(db_path: &Path) -> PathBuf {
+    let mut name = db_path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".backups");
+    db_path.with_file_name(name)
+}
+
+// 現在のファイルをタイムスタンプ付きでコピーし、古いものを MAX_BACKUPS まで削る
+pub fn create_backup(db_path: &Path) -> io::Result<()> {
+    if !db_path.exists() {
+        return Ok(());
+    }
+    let dir = backup_dir(db_path);
+    fs::create_dir_all(&dir)?;
+    let name = format!("{}.json", Local::now().format(TIMESTAMP_FORMAT));
+    fs::copy(db_path, dir.join(name))?;
+    prune_backups(db_path, MAX_BACKUPS)
+}
+
+// SQLite はファイルごとコピーせず、書き込む前のタスクを todos.json と同じ形式で todos.db.backups/ に書く
+pub fn backup_todos(db_path: &Path, todos: &[Todo], projects: &ProjectSettings) -> io::Result<()> {
+    let dir = backup_dir(db_path);
+    fs::create_dir_all(&dir)?;
+    let data = encode_todos(todos, projects).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let name = format!("{}.json", Local::now().format(TIMESTAMP_FORMAT));
+    fs::write(dir.join(name), data)?;
+    prune_backups(db_path, MAX_BACKUPS)
+}
+
+// 新しい順に並べたバックアップの一覧
+pub fn list_backups(db_path: &Path) -> io::Result<Vec<Backup>> {
+    let dir = backup_dir(db_path);
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut backups: Vec<Backup> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let stem = path.file_stem()?.to_str()?;
+            let created_at = NaiveDateTime::parse_from_str(stem, TIMESTAMP_FORMAT).ok()?;
+            Some(Backup { path, created_at })
+        })
+        .collect();
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+    Ok(backups)
+}
+
+fn prune_backups(db_path: &Path, keep: usize) -> io::Result<()> {
+    for backup in list_backups(db_path)?.into_iter().skip(keep) {
+        fs::remove_file(backup.path)?;
+    }
+    Ok(())
+}
+
+// バックアップの中身を検証してから現在のファイルと置き換える(置き換え前の状態もバックアップする)
+pub fn restore_backup(db_path: &Path, backup: &Backup) -> io::Result<()> {
+    let data = fs::read(&backup.path)?;
+    let (mut todos, projects, _) = decode_todos(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    if is_sqlite_path(db_path) {
+        return restore_into_sqlite(db_path, &mut todos, projects);
+    }
+    create_backup(db_path)?;
+    write_atomic(db_path, &data)
+}
+
+// SQLite へは普段の保存と同じく書き込む(置き換え前の状態は保存時にバックアップされる)
+fn restore_into_sqlite(db_path: &Path, todos: &mut Vec<Todo>, projects: ProjectSettings) -> io::Result<()> {
+    let to_io = |e: StoreError| io::Error::other(e.to_string());
+    let mut store = Store::new(db_path.to_path_buf());
+    store.load().map_err(to_io)?;
+    store.set_projects(projects);
+    match store.save(todos).map_err(to_io)? {
+        SaveOutcome::Saved => Ok(()),
+        SaveOutcome::Merged | SaveOutcome::Conflicts => Err(io::Error::other(format!(
+            "{} was changed by another ltd while restoring; try again",
+            db_path.display()
+        ))),
+    }
+}
+
+// 読み込めるバックアップのうち最も新しいものに戻す
+pub fn restore_newest_backup(db_path: &Path) -> io::Result<Option<NaiveDateTime>> {
+    for backup in list_backups(db_path)? {
+        if load_todos_from(&backup.path).is_ok() {
+            restore_backup(db_path, &backup)?;
+            return Ok(Some(backup.created_at));
+        }
+    }
+    Ok(None)
+}
+
+// 形式を変換する前の状態を todos.json.v<元のバージョン>-<timestamp>.bak として残す。
+// ローテーションされる通常のバックアップとは別なので消えない
+pub fn create_migration_backup(db_path: &Path, from_version: u64) -> io::Result<PathBuf> {
+    let mut name = db_path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(format!(".v{}-{}.bak", from_version, Local::now().format(TIMESTAMP_FORMAT)));
+    let backup = db_path.with_file_name(name);
+    fs::copy(db_path, &backup)?;
+    Ok(backup)
+}
+
+// 壊れたファイルを消さずに todos.json.broken-<timestamp> へ退避する
+pub fn set_aside(db_path: &Path) -> io::Result<PathBuf> {
+    let mut name = db_path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(format!(".broken-{}", Local::now().format(TIMESTAMP_FORMAT)));
+    let aside = db_path.with_file_name(name);
+    fs::rename(db_path, &aside)?;
+    Ok(aside)
+}
+
+fn task_count(backup: &Backup) -> Option<usize> {
+    load_todos_from(&backup.path).ok().map(|todos| todos.len())
+}
+
+// `ltd restore` は一覧を表示し、`ltd restore <n>` で n 番目に戻す
+pub fn restore_command(db_path: &Path, index: Option<usize>) -> io::Result<()> {
+    let backups = list_backups(db_path)?;
+    match index {
+        None => {
+            if backups.is_empty() {
+                println!("No backups for {}", db_path.display());
+                return Ok(());
+            }
+            println!("Backups for {}:", db_path.display());
+            for (i, backup) in backups.iter().enumerate() {
+                let tasks = match task_count(backup) {
+                    Some(count) => format!("{} tasks", count),
+                    None => String::from("unreadable"),
+                };
+                println!(
+                    "{:>3}  {}  {}",
+                    i + 1,
+                    backup.created_at.format("%Y-%m-%d %H:%M:%S"),
+                    tasks
+                );
+            }
+            println!("Run `ltd restore <n>` to roll back.");
+            Ok(())
+        }
+        Some(index) => {
+            let backup = index
+                .checked_sub(1)
+                .and_then(|i| backups.get(i))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No backup #{}", index)))?;
+            restore_backup(db_path, backup)?;
+            println!(
+                "Restored {} from backup taken at {}",
+                db_path.display(),
+                backup.created_at.format("%Y-%m-%d %H:%M:%S")
+            );
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "ltd", version, about = "Lazy Todo")]
+pub struct Cli {
+    /// Use this file as the todo store (overrides LTD_FILE)
+    #[arg(long, global = true, conflicts_with = "local")]
+    pub file: Option<PathBuf>,
+
+    /// Use the nearest .ltd.json found by walking up from the current directory
+    #[arg(long, global = true)]
+    pub local: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// List backups, or roll the store back to backup <N>
+    Restore {
+        /// Backup number as shown by `ltd restore`
+        index: Option<usize>,
+    },
+    /// Copy the current store into another storage format
+    Migrate {
+        /// Storage format to copy into
+        #[arg(long, value_enum)]
+        to: StorageFormat,
+        /// Destination file (defaults to the current file with a .db/.json extension)
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Add a task without opening the TUI
+    Add {
+        /// Title; #words become tags
+        title: String,
+        #[command(flatten)]
+        fields: TaskFields,
+    },
+    /// Print the tasks as a tree with their IDs
+    List {
+        /// Only tasks in this project
+        #[arg(long)]
+        project: Option<String>,
+        /// Only tasks with this tag (repeat for several; all must match)
+        #[arg(short, long = "tag")]
+        tags: Vec<String>,
+        /// Hide tasks that are done (completion rates still count done subtasks)
+        #[arg(long)]
+        pending: bool,
+        /// Order of the top-level tasks
+        #[arg(long, value_enum)]
+        sort: Option<SortArg>,
+        /// Output format (default: table on a terminal, plain otherwise)
+        #[arg(long, value_enum)]
+        format: Option<ListFormat>,
+    },
+    /// Mark a task done (recurring tasks move on to their next deadline)
+    Done {
+        /// Task ID as shown by `ltd list` (any unique ending of the full ID)
+        id: String,
+    },
+    /// Change fields of a task; fields that are not given stay as they are
+    Edit {
+        id: String,
+        #[arg(long)]
+        title: Option<String>,
+        #[command(flatten)]
+        fields: TaskFields,
+        /// Mark the task as not done
+        #[arg(long)]
+        undone: bool,
+    },
+    /// Delete a task and its subtasks
+    Rm { id: String },
+    /// Add the tasks in a file written by another tool
+    Import {
+        #[arg(long, value_enum)]
+        format: ExchangeFormat,
+        /// File to read (standard input if omitted or "-")
+        #[arg(value_name = "FILE")]
+        input: Option<PathBuf>,
+    },
+    /// Write every task in a format another tool can read
+    Export {
+        #[arg(long, value_enum)]
+        format: ExchangeFormat,
+        /// File to write (standard output if omitted)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Work with subtasks
+    Sub {
+        #[command(subcommand)]
+        command: SubCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SubCommand {
+    /// Add a subtask under task <ID> (which can itself be a subtask)
+    Add { id: String, title: String },
+}
+
+// add と edit で共通の項目
+#[derive(Args, Default)]
+pub struct TaskFields {
+    /// Notes shown in the details pane
+    #[arg(short, long)]
+    pub content: Option<String>,
+    /// none, low, medium, high or urgent
+    #[arg(short, long)]
+    pub priority: Option<String>,
+    /// Deadline such as 2024-05-01, tomorrow 17:00, fri or +3d ("none" clears it)
+    #[arg(short, long)]
+    pub deadline: Option<String>,
+    /// Repeat rule such as daily, weekly mon,thu or every 3d after completion ("none" clears it)
+    #[arg(short, long)]
+    pub repeat: Option<String>,
+    /// Tags separated by spaces or commas (replaces the existing tags on edit)
+    #[arg(short, long)]
+    pub tags: Option<String>,
+    /// Project to put the task in (top-level tasks only)
+    #[arg(long)]
+    pub project: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SortArg {
+    Completion,
+    Deadline,
+    Priority,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ListFormat {
+    /// One JSON array with every task (subtasks nested)
+    Json,
+    /// One JSON object per top-level task per line
+    Ndjson,
+    /// Aligned columns that fit the terminal width
+    Table,
+    /// One line per task, for grep and shell scripts
+    Plain,
+}
+
+// import / export で読み書きできる形式
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ExchangeFormat {
+    /// todo.txt (http://todotxt.org)
+    Todotxt,
+    /// GitHub-style checklists, subtasks nested under their parent
+    Markdown,
+    /// iCalendar (.ics) VTODO items
+    Ics,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum StorageFormat {
+    Json,
+    Sqlite,
+}
@@ -0,0 +1,284 @@
+use crate::add_task::add_task;
+use crate::cli::{ListFormat, SortArg, TaskFields};
+use crate::config::config;
+use crate::deadline::format_deadline;
+use crate::delete_task::delete_task;
+use crate::history::{history_path, History};
+use crate::output::{self, resolve_format};
+use crate::priority::Priority;
+use crate::project::{ListView, DEFAULT_PROJECT};
+use crate::recurrence::parse_schedule;
+use crate::sort::{sort_todos, SortMode};
+use crate::store::{SaveOutcome, Store};
+use crate::tags::{extract_tags, format_tags, parse_tags, TagFilter, TagMatch};
+use crate::todo::Todo;
+use crate::tree::{self, TreePath};
+use chrono::Utc;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+// 一覧に出す ID の長さ。ULID の末尾はランダムなので、同じ時刻に作ったタスクでも重なりにくい
+const SHORT_ID_LEN: usize = 6;
+
+pub fn short_id(id: &str) -> String {
+    id[id.len().saturating_sub(SHORT_ID_LEN)..].to_lowercase()
+}
+
+// TUI を開かずに1回だけ読み書きする。履歴は TUI と同じものに残すので、あとで TUI の u で戻せる
+pub struct Session {
+    pub store: Store,
+    pub todos: Vec<Todo>,
+    pub history: History,
+}
+
+impl Session {
+    pub fn open(db_path: &Path) -> Result<Self, String> {
+        let mut store = Store::new(db_path.to_path_buf());
+        let todos = store.load().map_err(|e| e.to_string())?;
+        let persisted_history = config().persist_history.then(|| history_path(store.path()));
+        Ok(Session {
+            store,
+            todos,
+            history: History::new(config().history_limit, persisted_history),
+        })
+    }
+
+    // 完全な ID か、その末尾(大文字小文字は問わない)で1つに決まるタスク
+    pub fn find(&self, id: &str) -> Result<TreePath, String> {
+        fn collect<'a>(todos: &'a [Todo], id: &str, found: &mut Vec<&'a Todo>) {
+            for todo in todos {
+                if !id.is_empty() && todo.id.to_uppercase().ends_with(id) {
+                    found.push(todo);
+                }
+                collect(&todo.subtasks, id, found);
+            }
+        }
+        let mut found = vec![];
+        collect(&self.todos, &id.trim().to_uppercase(), &mut found);
+        match found.as_slice() {
+            [] => Err(format!("no task with ID {}", id)),
+            [todo] => tree::path_of(&self.todos, &todo.id).ok_or_else(|| format!("no task with ID {}", id)),
+            _ => {
+                let candidates: Vec<String> = found.iter().map(|todo| format!("{} {}", todo.id, todo.title)).collect();
+                Err(format!("ID {} matches more than one task:\n  {}", id, candidates.join("\n  ")))
+            }
+        }
+    }
+
+    pub fn save(mut self) -> Result<(), String> {
+        match self.store.save(&mut self.todos) {
+            Ok(SaveOutcome::Saved | SaveOutcome::Merged) => Ok(()),
+            Ok(SaveOutcome::Conflicts) => Err("another ltd edited the same task; nothing was saved".to_string()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+fn parse_priority(input: &str) -> Result<Priority, String> {
+    Priority::parse(input).ok_or_else(|| {
+        let names: Vec<&str> = Priority::ALL.iter().map(|p| p.to_str()).collect();
+        format!("unknown priority \"{}\" (use one of {})", input, names.join(", "))
+    })
+}
+
+// 「none」は空欄と同じで、期限や繰り返しを外す
+fn clearable(input: &str) -> &str {
+    if input.trim().eq_ignore_ascii_case("none") {
+        ""
+    } else {
+        input
+    }
+}
+
+pub fn add_command(db_path: &Path, title: &str, fields: TaskFields) -> Result<(), String> {
+    let mut session = Session::open(db_path)?;
+    let (title, mut tags) = extract_tags(title);
+    if title.is_empty() {
+        return Err("the title is empty".to_string());
+    }
+    if let Some(input) = &fields.tags {
+        tags = parse_tags(&format!("{} {}", format_tags(&tags), input));
+    }
+    let priority = fields.priority.as_deref().map(parse_priority).transpose()?.unwrap_or_default();
+    let (deadline, recurrence) = parse_schedule(
+        clearable(fields.deadline.as_deref().unwrap_or_default()),
+        clearable(fields.repeat.as_deref().unwrap_or_default()),
+    )?;
+    add_task(
+        &mut session.todos,
+        title,
+        fields.content.unwrap_or_default(),
+        priority,
+        tags,
+        deadline,
+        recurrence,
+        fields.project.unwrap_or_else(|| DEFAULT_PROJECT.to_string()),
+        &mut session.history,
+    );
+    let added = session.todos.last().cloned();
+    session.save()?;
+    if let Some(todo) = added {
+        println!("Added {} {}", short_id(&todo.id), todo.title);
+    }
+    Ok(())
+}
+
+pub fn list_command(
+    db_path: &Path,
+    project: Option<String>,
+    tags: Vec<String>,
+    pending: bool,
+    sort: Option<SortArg>,
+    format: Option<ListFormat>,
+) -> Result<(), String> {
+    let todos = Store::new(db_path.to_path_buf()).load().map_err(|e| e.to_string())?;
+    let mut view = ListView::default();
+    view.project = project;
+    view.tags = TagFilter {
+        selected: parse_tags(&tags.join(" ")),
+        mode: TagMatch::All,
+    };
+    let shown = listed_todos(&todos, &view, pending, sort);
+    let show_project = view.project.is_none();
+    let now = Utc::now();
+    let lines = match resolve_format(format, io::stdout().is_terminal()) {
+        ListFormat::Json => vec![output::json(&shown, pending, now)],
+        ListFormat::Ndjson => output::ndjson(&shown, pending, now),
+        ListFormat::Table => {
+            let width = crossterm::terminal::size().map_or(80, |(width, _)| width as usize);
+            output::table_lines(&shown, show_project, pending, width, now)
+        }
+        ListFormat::Plain => output::plain_lines(&shown, show_project, pending, now),
+    };
+    for line in lines {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+// 一覧に出すタスク。親のタスクを view で絞り込んで並べ替え、pending なら完了したものを外す。
+// 完了したサブタスクは達成率の計算に要るので残し、出力するときに外す
+pub fn listed_todos(todos: &[Todo], view: &ListView, pending: bool, sort: Option<SortArg>) -> Vec<Todo> {
+    let mut shown: Vec<Todo> = todos
+        .iter()
+        .filter(|todo| view.matches(todo) && !(pending && todo.done))
+        .cloned()
+        .collect();
+    if let Some(sort) = sort {
+        let mode = match sort {
+            SortArg::Completion => SortMode::ByCompletion,
+            SortArg::Deadline => SortMode::ByDeadline,
+            SortArg::Priority => SortMode::ByPriority,
+        };
+        sort_todos(&mut shown, mode);
+    }
+    shown
+}
+
+pub fn list_lines(todos: &[Todo], view: &ListView, pending: bool, sort: Option<SortArg>) -> Vec<String> {
+    output::plain_lines(&listed_todos(todos, view, pending, sort), view.project.is_none(), pending, Utc::now())
+}
+
+pub fn done_command(db_path: &Path, id: &str) -> Result<(), String> {
+    let mut session = Session::open(db_path)?;
+    let path = session.find(id)?;
+    let todo = tree::get(&session.todos, &path).cloned().ok_or_else(|| format!("no task with ID {}", id))?;
+    if todo.done {
+        println!("{} is already done", todo.title);
+        return Ok(());
+    }
+    // 繰り返しのタスクは期限が次の回へ進む
+    let next = session.history.update(&mut session.todos, &path, "Toggle", |todo| todo.toggle_done(Utc::now()));
+    session.save()?;
+    match next.flatten() {
+        Some(next) => println!("Done {}; next due {}", todo.title, format_deadline(next)),
+        None => println!("Done {}", todo.title),
+    }
+    Ok(())
+}
+
+pub fn edit_command(db_path: &Path, id: &str, title: Option<String>, fields: TaskFields, undone: bool) -> Result<(), String> {
+    let mut session = Session::open(db_path)?;
+    let path = session.find(id)?;
+    let todo = tree::get(&session.todos, &path).cloned().ok_or_else(|| format!("no task with ID {}", id))?;
+    if fields.project.is_some() && path.len() > 1 {
+        return Err("subtasks stay in the project of their parent task".to_string());
+    }
+
+    let mut tags = fields.tags.as_deref().map_or_else(|| todo.tags.clone(), parse_tags);
+    let title = match title {
+        Some(input) => {
+            let (title, title_tags) = extract_tags(&input);
+            if title.is_empty() {
+                return Err("the title is empty".to_string());
+            }
+            tags = parse_tags(&format!("{} {}", format_tags(&tags), format_tags(&title_tags)));
+            title
+        }
+        None => todo.title.clone(),
+    };
+    let priority = fields.priority.as_deref().map(parse_priority).transpose()?.unwrap_or(todo.priority);
+    // 渡されなかった方は今の値を入力欄に戻したのと同じに扱う(TUI の e と同じ)
+    let deadline = fields.deadline.unwrap_or_else(|| todo.deadline.map(format_deadline).unwrap_or_default());
+    let recurrence = fields.repeat.unwrap_or_else(|| todo.recurrence.as_ref().map(ToString::to_string).unwrap_or_default());
+    let (deadline, recurrence) = parse_schedule(clearable(&deadline), clearable(&recurrence))?;
+
+    let changed = session.history.update(&mut session.todos, &path, "Edit", |edited| {
+        edited.title = title;
+        if let Some(content) = fields.content {
+            edited.content = content;
+        }
+        edited.tags = tags;
+        edited.priority = priority;
+        edited.deadline = deadline;
+        edited.recurrence = recurrence;
+        if let Some(project) = fields.project {
+            edited.project = project;
+        }
+        if undone {
+            edited.done = false;
+        }
+        if *edited == todo {
+            return None;
+        }
+        edited.date_time = Utc::now().to_rfc3339();
+        Some(edited.title.clone())
+    });
+    let Some(Some(title)) = changed else {
+        println!("Nothing to change in {}", todo.title);
+        return Ok(());
+    };
+    session.save()?;
+    println!("Edited {} {}", short_id(&todo.id), title);
+    Ok(())
+}
+
+pub fn rm_command(db_path: &Path, id: &str) -> Result<(), String> {
+    let mut session = Session::open(db_path)?;
+    let path = session.find(id)?;
+    let removed = delete_task(&mut session.todos, &path, &mut session.history);
+    session.save()?;
+    if let Some(todo) = removed {
+        println!("Deleted {} {}", short_id(&todo.id), todo.title);
+    }
+    Ok(())
+}
+
+pub fn sub_add_command(db_path: &Path, id: &str, title: &str) -> Result<(), String> {
+    let mut session = Session::open(db_path)?;
+    let path = session.find(id)?;
+    let title = title.trim();
+    if title.is_empty() {
+        return Err("the title is empty".to_string());
+    }
+    let added = session.history.update(&mut session.todos, &path, "Add subtask to", |todo| {
+        todo.add_subtask(title.to_string());
+        todo.expanded = true;
+        (todo.subtasks.last().map(|subtask| subtask.id.clone()), todo.title.clone())
+    });
+    session.save()?;
+    if let Some((Some(id), parent)) = added {
+        println!("Added {} {} under {}", short_id(&id), title, parent);
+    }
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use crate::cli::StorageFormat;
+use crate::deadline::Urgency;
+use crate::priority::Priority;
+use chrono::Duration;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tui::style::Color;
+
+// 起動時に一度だけ読み込む設定。読み込む前(や設定ファイルが無いとき)は既定値
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+pub fn set_config(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+// 設定ファイルの書式(config.toml)
+//
+// [priorities.urgent]
+// name = "今すぐ"
+// color = "#ff00ff"
+//
+// [deadlines]
+// due_soon_hours = 24
+// overdue_color = "red"
+//
+// [history]
+// limit = 100
+// persist = true
+//
+// [storage]
+// format = "sqlite"
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    priorities: HashMap<Priority, PriorityStyleFile>,
+    deadlines: DeadlinesFile,
+    history: HistoryFile,
+    storage: StorageFile,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct StorageFile {
+    format: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct HistoryFile {
+    limit: Option<usize>,
+    persist: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct DeadlinesFile {
+    due_soon_hours: Option<u32>,
+    upcoming_days: Option<u32>,
+    overdue_color: Option<String>,
+    due_soon_color: Option<String>,
+    upcoming_color: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PriorityStyleFile {
+    name: Option<String>,
+    color: Option<String>,
+}
+
+pub struct Config {
+    priorities: HashMap<Priority, (String, Color)>,
+    // 期限までこれを切ったら due soon、upcoming の色にする
+    pub due_soon: Duration,
+    pub upcoming: Duration,
+    overdue_color: Color,
+    due_soon_color: Color,
+    upcoming_color: Color,
+    // 取り消せる操作の数と、履歴を次の起動に持ち越すか
+    pub history_limit: usize,
+    pub persist_history: bool,
+    // 既定の保存先(todos.json / todos.db)の形式。--file や LTD_FILE は拡張子で決まる
+    pub storage: StorageFormat,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let priorities = Priority::ALL
+            .into_iter()
+            .map(|priority| {
+                let style = match priority {
+                    Priority::None => ("None", Color::DarkGray),
+                    Priority::Low => ("Low", Color::Green),
+                    Priority::Medium => ("Medium", Color::Yellow),
+                    Priority::High => ("High", Color::Red),
+                    Priority::Urgent => ("Urgent", Color::Magenta),
+                };
+                (priority, (style.0.to_string(), style.1))
+            })
+            .collect();
+        Config {
+            priorities,
+            due_soon: Duration::hours(24),
+            upcoming: Duration::days(7),
+            overdue_color: Color::Red,
+            due_soon_color: Color::Yellow,
+            upcoming_color: Color::Cyan,
+            history_limit: 100,
+            persist_history: false,
+            storage: StorageFormat::Json,
+        }
+    }
+}
+
+impl Config {
+    pub fn priority_name(&self, priority: Priority) -> &str {
+        &self.priorities[&priority].0
+    }
+
+    pub fn priority_color(&self, priority: Priority) -> Color {
+        self.priorities[&priority].1
+    }
+
+    // 期限がまだ先なら色は付けない
+    pub fn urgency_color(&self, urgency: Urgency) -> Option<Color> {
+        match urgency {
+            Urgency::Overdue => Some(self.overdue_color),
+            Urgency::DueSoon => Some(self.due_soon_color),
+            Urgency::Upcoming => Some(self.upcoming_color),
+            Urgency::Later => None,
+        }
+    }
+}
+
+// LTD_CONFIG > $XDG_CONFIG_HOME/ltd/config.toml
+pub fn config_path() -> PathBuf {
+    if let Some(path) = env::var_os("LTD_CONFIG").filter(|path| !path.is_empty()) {
+        return PathBuf::from(path);
+    }
+    let dir = match env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")).join(".config"),
+    };
+    dir.join("ltd").join("config.toml")
+}
+
+pub fn load_config(path: &Path) -> Result<Config, String> {
+    let mut config = Config::default();
+    if !path.exists() {
+        return Ok(config);
+    }
+    let data = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let file: ConfigFile = toml::from_str(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let color = |color: String| parse_color(&color).ok_or_else(|| format!("{}: unknown color \"{}\"", path.display(), color));
+    for (priority, style) in file.priorities {
+        let entry = config.priorities.get_mut(&priority).unwrap();
+        if let Some(name) = style.name {
+            entry.0 = name;
+        }
+        if let Some(style_color) = style.color {
+            entry.1 = color(style_color)?;
+        }
+    }
+
+    let deadlines = file.deadlines;
+    if let Some(hours) = deadlines.due_soon_hours {
+        config.due_soon = Duration::hours(hours as i64);
+    }
+    if let Some(days) = deadlines.upcoming_days {
+        config.upcoming = Duration::days(days as i64);
+    }
+    if let Some(overdue_color) = deadlines.overdue_color {
+        config.overdue_color = color(overdue_color)?;
+    }
+    if let Some(due_soon_color) = deadlines.due_soon_color {
+        config.due_soon_color = color(due_soon_color)?;
+    }
+    if let Some(upcoming_color) = deadlines.upcoming_color {
+        config.upcoming_color = color(upcoming_color)?;
+    }
+    if let Some(limit) = file.history.limit {
+        config.history_limit = limit;
+    }
+    if let Some(persist) = file.history.persist {
+        config.persist_history = persist;
+    }
+    if let Some(format) = file.storage.format {
+        config.storage = match format.as_str() {
+            "json" => StorageFormat::Json,
+            "sqlite" => StorageFormat::Sqlite,
+            _ => return Err(format!("{}: unknown storage format \"{}\"", path.display(), format)),
+        };
+    }
+    Ok(config)
+}
+
+// "red" や "light-blue" などの色名か "#rrggbb"
+pub fn parse_color(s: &str) -> Option<Color> {
+    let s = s.trim().to_lowercase().replace(['-', '_', ' '], "");
+    if let Some(hex) = s.strip_prefix('#') {
+        // バイト数で切り出すので、ASCII 以外(打ち間違いの é など)は先に弾く
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+    let color = match s.as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
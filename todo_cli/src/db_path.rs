@@ -0,0 +1,47 @@
+use crate::cli::StorageFormat;
+use std::env;
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "ltd";
+const DEFAULT_FILE: &str = "todos.json";
+const DEFAULT_SQLITE_FILE: &str = "todos.db";
+const LOCAL_FILE: &str = ".ltd.json";
+const LOCAL_SQLITE_FILE: &str = ".ltd.db";
+
+// --file > --local > LTD_FILE > $XDG_DATA_HOME/ltd/todos.json の順で保存先を決める
+// (設定の [storage] format が sqlite なら .ltd.db / todos.db の方。どちらのファイルがあるかでは切り替えない)
+pub fn resolve_db_path(file: Option<PathBuf>, local: bool, format: StorageFormat) -> PathBuf {
+    let (default_file, local_file) = match format {
+        StorageFormat::Json => (DEFAULT_FILE, LOCAL_FILE),
+        StorageFormat::Sqlite => (DEFAULT_SQLITE_FILE, LOCAL_SQLITE_FILE),
+    };
+    if let Some(file) = file {
+        return file;
+    }
+    if local {
+        let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        return find_local_file(&cwd, local_file).unwrap_or_else(|| cwd.join(local_file));
+    }
+    if let Some(file) = env::var_os("LTD_FILE").filter(|file| !file.is_empty()) {
+        return PathBuf::from(file);
+    }
+    data_dir().join(APP_DIR).join(default_file)
+}
+
+// gitと同じように親ディレクトリを遡って .ltd.json(または .ltd.db)を探す
+pub fn find_local_file(start: &Path, name: &str) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+fn data_dir() -> PathBuf {
+    match env::var_os("XDG_DATA_HOME").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".local")
+            .join("share"),
+    }
+}
@@ -0,0 +1,271 @@
+use crate::config::config;
+use chrono::prelude::*;
+use chrono::{Duration, Months, NaiveDate, NaiveDateTime, NaiveTime};
+use tui::style::Color;
+
+const DISPLAY_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+// 入力欄やファイルに出す形式。parse_deadline でそのまま読み戻せる
+pub fn format_deadline(deadline: DateTime<Utc>) -> String {
+    deadline.with_timezone(&Local).format(DISPLAY_FORMAT).to_string()
+}
+
+// 期限がどれだけ迫っているか。境目は設定ファイルで変えられる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Overdue,
+    DueSoon,
+    Upcoming,
+    Later,
+}
+
+impl Urgency {
+    pub fn of(deadline: DateTime<Utc>, now: DateTime<Utc>) -> Self {
+        let config = config();
+        let remaining = deadline - now;
+        if remaining < Duration::zero() {
+            Urgency::Overdue
+        } else if remaining <= config.due_soon {
+            Urgency::DueSoon
+        } else if remaining <= config.upcoming {
+            Urgency::Upcoming
+        } else {
+            Urgency::Later
+        }
+    }
+
+    pub fn color(self) -> Option<Color> {
+        config().urgency_color(self)
+    }
+}
+
+// 一覧に出す残り時間。「in 2d」「3h overdue」「today」のように短く
+pub fn describe_remaining(deadline: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let remaining = deadline - now;
+    if remaining < Duration::zero() {
+        return format!("{} overdue", short_duration(-remaining));
+    }
+    let days = (deadline.with_timezone(&Local).date_naive() - now.with_timezone(&Local).date_naive()).num_days();
+    match days {
+        0 if remaining < Duration::hours(1) => format!("in {}", short_duration(remaining)),
+        0 => "today".to_string(),
+        1 => "tomorrow".to_string(),
+        _ if days < 14 => format!("in {}d", days),
+        _ => format!("in {}w", days / 7),
+    }
+}
+
+fn short_duration(duration: Duration) -> String {
+    if duration < Duration::hours(1) {
+        format!("{}m", duration.num_minutes().max(1))
+    } else if duration < Duration::days(1) {
+        format!("{}h", duration.num_hours())
+    } else if duration < Duration::days(14) {
+        format!("{}d", duration.num_days())
+    } else {
+        format!("{}w", duration.num_weeks())
+    }
+}
+
+// 期限の入力を解釈する。空なら期限なし
+//
+// 受け付けるもの:
+//   2024-05-01 / 2024/05/01 / 05-01 / 2024-05-01 17:00 / 2024-05-01T17:00:00+09:00
+//   today / tomorrow / fri / next fri / end of week / end of month / end of year
+//   +3d / +2w / +1m(月) / +1y / +5h / +30min / in 3 days
+// 日付の後ろには 17:00 や 5pm のような時刻を付けられる(時刻だけなら今日)
+pub fn parse_deadline(input: &str, now: DateTime<Local>) -> Result<Option<DateTime<Utc>>, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(None);
+    }
+    if let Ok(deadline) = DateTime::parse_from_rfc3339(input) {
+        return Ok(Some(deadline.with_timezone(&Utc)));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(input, format) {
+            return to_utc(naive).map(Some);
+        }
+    }
+
+    let lower = input.to_lowercase();
+    let words: Vec<&str> = lower.split_whitespace().collect();
+    if let Some(deadline) = parse_offset(&words, now)? {
+        return Ok(Some(deadline));
+    }
+
+    // 末尾の時刻(「at 17:00」の at も許す)と、その前の日付に分ける
+    let (date_words, time) = match words.split_last() {
+        Some((last, rest)) => match parse_time(last) {
+            Some(time) => (rest.strip_suffix(&["at"]).unwrap_or(rest), Some(time)),
+            None => (&words[..], None),
+        },
+        None => (&words[..], None),
+    };
+    let today = now.date_naive();
+    let date = if date_words.is_empty() {
+        today
+    } else {
+        parse_date(&date_words.join(" "), today).ok_or_else(|| format!("can't understand deadline \"{}\"", input))?
+    };
+    let time = time.unwrap_or_else(end_of_day);
+    to_utc(date.and_time(time)).map(Some)
+}
+
+// 他の形式に書き出すときの期限。日の終わりなら日付だけ、それ以外は時刻も付ける(parse_absolute_deadline で読み戻せる)
+pub fn format_compact_deadline(deadline: DateTime<Utc>) -> String {
+    let local = deadline.with_timezone(&Local);
+    if local.time() == end_of_day() {
+        local.format("%Y-%m-%d").to_string()
+    } else {
+        local.format("%Y-%m-%dT%H:%M").to_string()
+    }
+}
+
+// 古いファイルの期限の文字列のように、今の日時によって意味が変わらないものだけを読む
+pub fn parse_absolute_deadline(input: &str) -> Option<DateTime<Utc>> {
+    let input = input.trim();
+    let has_year = input.split(['-', '/']).next().is_some_and(|year| year.len() == 4);
+    if !has_year {
+        return None;
+    }
+    parse_deadline(input, Local::now()).ok().flatten()
+}
+
+// 時刻を書かなかった期限はその日の終わり扱い
+pub fn end_of_day() -> NaiveTime {
+    NaiveTime::from_hms_opt(23, 59, 0).unwrap()
+}
+
+pub fn to_utc(naive: NaiveDateTime) -> Result<DateTime<Utc>, String> {
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|deadline| deadline.with_timezone(&Utc))
+        .ok_or_else(|| format!("{} does not exist in the local time zone", naive.format(DISPLAY_FORMAT)))
+}
+
+// +3d や in 3 days。日より大きい単位は日付だけ動かし、時刻は後ろに書いたもの(無ければ日の終わり)
+fn parse_offset(words: &[&str], now: DateTime<Local>) -> Result<Option<DateTime<Utc>>, String> {
+    let (amount_and_unit, time) = match words {
+        [offset] if offset.starts_with('+') => (offset[1..].to_string(), None),
+        [offset, time] if offset.starts_with('+') => (offset[1..].to_string(), Some(*time)),
+        ["in", amount, unit] => (format!("{}{}", amount, unit), None),
+        ["in", amount, unit, time] => (format!("{}{}", amount, unit), Some(*time)),
+        _ => return Ok(None),
+    };
+    let split = amount_and_unit.find(|c: char| !c.is_ascii_digit()).unwrap_or(amount_and_unit.len());
+    let (amount, unit) = amount_and_unit.split_at(split);
+    let Ok(amount) = amount.parse::<u32>() else {
+        return Ok(None);
+    };
+    let time = match time {
+        Some(time) => Some(parse_time(time).ok_or_else(|| format!("can't understand time \"{}\"", time))?),
+        None => None,
+    };
+
+    let today = now.date_naive();
+    let date = match unit {
+        "min" | "mins" | "minute" | "minutes" | "h" | "hour" | "hours" if time.is_none() => {
+            let minutes = if unit.starts_with('h') { amount as i64 * 60 } else { amount as i64 };
+            let deadline = Duration::try_minutes(minutes).and_then(|offset| now.checked_add_signed(offset));
+            return deadline.map(|deadline| Some(deadline.with_timezone(&Utc))).ok_or_else(too_far);
+        }
+        "d" | "day" | "days" => today.checked_add_days(chrono::Days::new(amount as u64)),
+        "w" | "week" | "weeks" => amount.checked_mul(7).and_then(|days| today.checked_add_days(chrono::Days::new(days as u64))),
+        "m" | "mo" | "month" | "months" => today.checked_add_months(Months::new(amount)),
+        "y" | "year" | "years" => amount.checked_mul(12).and_then(|months| today.checked_add_months(Months::new(months))),
+        _ => return Ok(None),
+    };
+    let date = date.ok_or_else(too_far)?;
+    let time = time.unwrap_or_else(end_of_day);
+    to_utc(date.and_time(time)).map(Some)
+}
+
+fn too_far() -> String {
+    "deadline is too far in the future".to_string()
+}
+
+// 17:00 / 9:30 / 5pm / 5:30pm / noon / midnight
+fn parse_time(word: &str) -> Option<NaiveTime> {
+    match word {
+        "noon" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return NaiveTime::from_hms_opt(0, 0, 0),
+        _ => {}
+    }
+    let (clock, offset) = if let Some(clock) = word.strip_suffix("am") {
+        (clock, Some(0))
+    } else if let Some(clock) = word.strip_suffix("pm") {
+        (clock, Some(12))
+    } else {
+        (word, None)
+    };
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) if minute.len() == 2 => (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?),
+        // 「17」だけでは日付と区別できないので、am/pm の無い数字だけは時刻にしない
+        None if offset.is_some() => (clock.parse::<u32>().ok()?, 0),
+        _ => return None,
+    };
+    let hour = match offset {
+        Some(offset) if (1..=12).contains(&hour) => hour % 12 + offset,
+        Some(_) => return None,
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+fn parse_date(phrase: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match phrase {
+        "today" | "tonight" => return Some(today),
+        "tomorrow" | "tmr" => return today.succ_opt(),
+        "end of week" | "eow" => return Some(today + Duration::days(6 - today.weekday().num_days_from_monday() as i64)),
+        "end of month" | "eom" => {
+            let first = NaiveDate::from_ymd_opt(today.year(), today.month(), 1)?;
+            return first.checked_add_months(Months::new(1))?.pred_opt();
+        }
+        "end of year" | "eoy" => return NaiveDate::from_ymd_opt(today.year(), 12, 31),
+        _ => {}
+    }
+
+    // fri は今日を含めた次のその曜日、next fri は明日以降の次のその曜日
+    let (weekday, skip_today) = match phrase.strip_prefix("next ") {
+        Some(day) => (parse_weekday(day), true),
+        None => (parse_weekday(phrase), false),
+    };
+    if let Some(weekday) = weekday {
+        let mut days = (weekday.num_days_from_monday() as i64 - today.weekday().num_days_from_monday() as i64).rem_euclid(7);
+        if days == 0 && skip_today {
+            days = 7;
+        }
+        return Some(today + Duration::days(days));
+    }
+
+    for format in ["%Y-%m-%d", "%Y/%m/%d"] {
+        if let Ok(date) = NaiveDate::parse_from_str(phrase, format) {
+            return Some(date);
+        }
+    }
+    // 年を省いたら今年
+    for separator in ['-', '/'] {
+        if let Some((month, day)) = phrase.split_once(separator) {
+            if let (Ok(month), Ok(day)) = (month.parse(), day.parse()) {
+                return NaiveDate::from_ymd_opt(today.year(), month, day);
+            }
+        }
+    }
+    None
+}
+
+pub fn parse_weekday(word: &str) -> Option<Weekday> {
+    let weekday = match word {
+        "mon" | "monday" => Weekday::Mon,
+        "tue" | "tues" | "tuesday" => Weekday::Tue,
+        "wed" | "wednesday" => Weekday::Wed,
+        "thu" | "thur" | "thurs" | "thursday" => Weekday::Thu,
+        "fri" | "friday" => Weekday::Fri,
+        "sat" | "saturday" => Weekday::Sat,
+        "sun" | "sunday" => Weekday::Sun,
+        _ => return None,
+    };
+    Some(weekday)
+}
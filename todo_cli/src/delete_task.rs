@@ -1,16 +1,7 @@
+use crate::history::History;
 use crate::todo::Todo;
-use tui::widgets::ListState;
-use std::collections::VecDeque;
 
-pub fn delete_task(filtered_todos: &mut Vec<Todo>, todos: &mut Vec<Todo>, state: &mut ListState, deleted_todos: &mut VecDeque<Todo>) {
-    if let Some(selected) = state.selected() {
-        if !filtered_todos.is_empty() {
-            let deleted_todo = filtered_todos.remove(selected);
-            deleted_todos.push_back(deleted_todo);
-            *todos = filtered_todos.clone();
-            if selected > 0 {
-                state.select(Some(selected - 1));
-            }
-        }
-    }
+// path はタスクの木の中の位置。サブタスクならその下の段ごと消える。消したタスクを返す
+pub fn delete_task(todos: &mut Vec<Todo>, path: &[usize], history: &mut History) -> Option<Todo> {
+    history.remove(todos, path, "Delete")
 }
@@ -0,0 +1,87 @@
+use crate::cli::ExchangeFormat;
+use crate::commands::Session;
+use crate::ical;
+use crate::markdown;
+use crate::store::Store;
+use crate::todo::{find_todo, new_id, write_atomic, Todo};
+use crate::todotxt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+// 他のツールの形式で書き出す。output が無ければ標準出力へ
+// 書き出せなかったもの(その形式に無い項目など)は標準エラーに知らせる
+pub fn export_command(db_path: &Path, format: ExchangeFormat, output: Option<PathBuf>) -> Result<(), String> {
+    let todos = Store::new(db_path.to_path_buf()).load().map_err(|e| e.to_string())?;
+    let (text, warnings) = match format {
+        ExchangeFormat::Todotxt => todotxt::export(&todos),
+        ExchangeFormat::Markdown => markdown::export(&todos),
+        ExchangeFormat::Ics => ical::export(&todos),
+    };
+    report(&warnings);
+    match output {
+        Some(path) => {
+            write_atomic(&path, text.as_bytes()).map_err(|e| format!("{}: {}", path.display(), e))?;
+            eprintln!("Exported {} tasks to {}", count_tasks(&todos), path.display());
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+// 読み込んだタスクを今の一覧の末尾に足す。input が無いか「-」なら標準入力から読む
+pub fn import_command(db_path: &Path, format: ExchangeFormat, input: Option<PathBuf>) -> Result<(), String> {
+    let text = match input.as_deref().filter(|path| *path != Path::new("-")) {
+        Some(path) => fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        None => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text).map_err(|e| e.to_string())?;
+            text
+        }
+    };
+    let (imported, mut warnings) = match format {
+        ExchangeFormat::Todotxt => todotxt::import(&text),
+        ExchangeFormat::Markdown => markdown::import(&text),
+        ExchangeFormat::Ics => ical::import(&text),
+    };
+
+    let mut session = Session::open(db_path)?;
+    let count = count_tasks(&imported);
+    let mut added: Vec<Todo> = vec![];
+    for mut todo in imported {
+        renew_taken_ids(&mut todo, &session.todos, &added, &mut warnings);
+        added.push(todo);
+    }
+    // 取り消すときは読み込んだ分をまとめて1回で戻す
+    session.history.append(&mut session.todos, added, format!("Import {} tasks", count));
+    report(&warnings);
+    session.save()?;
+    println!("Imported {} tasks", count);
+    Ok(())
+}
+
+// 同じファイルを2回読み込んだときなど、もう使われている ID は振り直す(ID が重なると一覧が壊れる)
+// added は同じファイルから先に読み込んだタスク
+fn renew_taken_ids(todo: &mut Todo, todos: &[Todo], added: &[Todo], warnings: &mut Vec<String>) {
+    let taken = find_todo(todos, &todo.id).is_some() || find_todo(added, &todo.id).is_some();
+    if todo.id.is_empty() || taken {
+        if !todo.id.is_empty() {
+            warnings.push(format!("\"{}\": ID {} is already in the store; imported as a new task", todo.title, todo.id));
+        }
+        todo.id = new_id();
+    }
+    for subtask in &mut todo.subtasks {
+        renew_taken_ids(subtask, todos, added, warnings);
+    }
+}
+
+// サブタスクも数える
+fn count_tasks(todos: &[Todo]) -> usize {
+    todos.iter().map(|todo| 1 + count_tasks(&todo.subtasks)).sum()
+}
+
+fn report(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("ltd: {}", warning);
+    }
+}
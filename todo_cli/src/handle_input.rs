@@ -1,172 +1,204 @@
 use crate::add_task::add_task;
+use crate::app::{App, Effect};
+use crate::backup::{restore_newest_backup, set_aside};
+use crate::deadline::{format_deadline, parse_deadline};
 use crate::delete_task::delete_task;
-use crate::input::{InputMode, PrioritySelection};
-use crate::search::search_todos;
-use crate::sort::{cycle_sort_mode, sort_todos, SortMode};
-use crate::todo::{save_todos, Todo};
-use crossterm::cursor::{Hide, Show};
-use crossterm::event::KeyCode;
-use crossterm::terminal::{disable_raw_mode, LeaveAlternateScreen};
-use crossterm::ExecutableCommand;
-use std::io;
+use crate::history::History;
+use crate::input::InputMode;
+use crate::recurrence::parse_schedule;
+use crate::search::search_results;
+use crate::sort::{cycle_sort_mode, SortMode};
+use crate::terminal::{EXIT_LOAD_FAILED, EXIT_OK};
+use crate::store::{SaveOutcome, Store};
+use crate::project::{picker_entries, ListView, PickerEntry};
+use crate::tags::{all_tags, extract_tags, format_tags, parse_tags};
+use crate::todo::{find_todo, Todo};
+use crate::tree::{self, visible_rows, TreePath};
+use chrono::{Local, Utc};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use std::time::{Duration, Instant};
-use tui::backend::CrosstermBackend;
-use tui::Terminal;
 use tui::widgets::ListState;
-use std::collections::VecDeque;
 
-static mut LAST_SPACE_PRESS: Option<Instant> = None;
-static mut DELETED_TODOS: VecDeque<Todo> = VecDeque::new();
-
-pub fn handle_input(
-    key: crossterm::event::KeyEvent,
-    todos: &mut Vec<Todo>,
-    filtered_todos: &mut Vec<Todo>,
-    state: &mut ListState,
-    input_mode: &mut InputMode,
-    input_title: &mut String,
-    input_content: &mut String,
-    input_priority: &mut PrioritySelection,
-    input_deadline: &mut String,
-    search_query: &mut String,
-    search_state: &mut ListState,
-    sort_mode: &mut SortMode,
-    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
-    subtask_state: &mut ListState,
-) -> Result<(), io::Error> {
+// キー1つ分の処理。端末に対してしてほしいことは Effect として返す
+pub fn handle_input(app: &mut App, key: KeyEvent) -> Vec<Effect> {
+    let App {
+        todos,
+        state,
+        input_mode,
+        input_title,
+        input_content,
+        input_priority,
+        input_deadline,
+        input_recurrence,
+        input_tags,
+        search_query,
+        search_state,
+        sort_mode,
+        subtask_state,
+        view,
+        tag_state,
+        status_message,
+        load_error,
+        store,
+        history,
+        last_space_press,
+    } = app;
+    let mut effects = vec![];
+    status_message.clear();
     match input_mode {
         InputMode::Normal => {
             if key.code == KeyCode::Char(' ') {
                 let now = Instant::now();
-                unsafe {
-                    if let Some(last_press) = LAST_SPACE_PRESS {
-                        if now.duration_since(last_press) < Duration::from_millis(500) {
-                            *input_mode = InputMode::Searching;
-                            terminal.backend_mut().execute(Hide)?;
-                            LAST_SPACE_PRESS = None;
-                        } else {
-                            LAST_SPACE_PRESS = Some(now);
-                        }
+                if let Some(last_press) = *last_space_press {
+                    if now.duration_since(last_press) < Duration::from_millis(500) {
+                        *input_mode = InputMode::Searching;
+                        *search_query = view.search.clone();
+                        search_state.select(Some(0));
+                        effects.push(Effect::HideCursor);
+                        *last_space_press = None;
                     } else {
-                        LAST_SPACE_PRESS = Some(now);
+                        *last_space_press = Some(now);
                     }
+                } else {
+                    *last_space_press = Some(now);
                 }
             } else {
-                unsafe {
-                    LAST_SPACE_PRESS = None;
-                }
+                *last_space_press = None;
                 match key.code {
                     KeyCode::Char('q') => {
-                        disable_raw_mode()?;
-                        terminal.backend_mut().execute(LeaveAlternateScreen)?;
-                        terminal.show_cursor()?;
-                        std::process::exit(0);
+                        return vec![Effect::Quit(EXIT_OK)];
+                    }
+                    // 検索の絞り込みを外す。カーソルはそのタスクのまま
+                    KeyCode::Esc if !view.search.is_empty() => {
+                        let path = selected_path(todos, view, state);
+                        view.search.clear();
+                        if let Some(path) = path {
+                            select_path(todos, view, state, &path);
+                        }
+                        *status_message = String::from("Search cleared");
                     }
                     KeyCode::Char('a') => {
                         *input_mode = InputMode::AddingTitle;
                     }
                     KeyCode::Char('d') => {
-                        unsafe {
-                            delete_task(filtered_todos, todos, state, &mut DELETED_TODOS);
+                        if let Some(path) = selected_path(todos, view, state) {
+                            if delete_task(todos, &path, history).is_some() {
+                                if let Some(selected) = state.selected().filter(|&selected| selected > 0) {
+                                    state.select(Some(selected - 1));
+                                }
+                            }
+                            save(todos, store, status_message);
                         }
-                        save_todos(todos);
                     }
-                    KeyCode::Char('u') => {
-                        unsafe {
-                            if let Some(todo) = DELETED_TODOS.pop_back() {
-                                filtered_todos.push(todo.clone());
-                                *todos = filtered_todos.clone();
-                                save_todos(todos);
+                    KeyCode::Char('u') => match history.undo(todos) {
+                        Some((label, focus)) => {
+                            save(todos, store, status_message);
+                            focus_task(todos, view, state, focus);
+                            if status_message.is_empty() {
+                                *status_message = format!("Undid: {}", label);
                             }
                         }
-                    }
-                    KeyCode::Char('e') => {
-                        if let Some(selected) = state.selected() {
-                            let (main_index, sub_index) = get_main_and_sub_index(filtered_todos, selected);
-                            if let Some(main_index) = main_index {
-                                if let Some(sub_index) = sub_index {
-                                    let subtask = &filtered_todos[main_index].subtasks[sub_index];
-                                    *input_mode = InputMode::EditingTitle(selected);
-                                    *input_title = subtask.title.clone();
-                                    *input_content = subtask.content.clone();
-                                    *input_priority = match subtask.priority.as_str() {
-                                        "low" => PrioritySelection::Low,
-                                        "medium" => PrioritySelection::Medium,
-                                        "high" => PrioritySelection::High,
-                                        _ => PrioritySelection::Low,
-                                    };
-                                    *input_deadline = subtask.deadline.clone();
-                                } else {
-                                    let todo = &filtered_todos[main_index];
-                                    *input_mode = InputMode::EditingTitle(selected);
-                                    *input_title = todo.title.clone();
-                                    *input_content = todo.content.clone();
-                                    *input_priority = match todo.priority.as_str() {
-                                        "low" => PrioritySelection::Low,
-                                        "medium" => PrioritySelection::Medium,
-                                        "high" => PrioritySelection::High,
-                                        _ => PrioritySelection::Low,
-                                    };
-                                    *input_deadline = todo.deadline.clone();
-                                }
+                        None => *status_message = String::from("Nothing to undo"),
+                    },
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => match history.redo(todos) {
+                        Some((label, focus)) => {
+                            save(todos, store, status_message);
+                            focus_task(todos, view, state, focus);
+                            if status_message.is_empty() {
+                                *status_message = format!("Redid: {}", label);
                             }
                         }
+                        None => *status_message = String::from("Nothing to redo"),
+                    },
+                    KeyCode::Char('e') => {
+                        if let Some(todo) = selected_path(todos, view, state).and_then(|path| tree::get(todos, &path)) {
+                            *input_mode = InputMode::EditingTitle(todo.id.clone());
+                            *input_title = todo.title.clone();
+                            *input_content = todo.content.clone();
+                            *input_priority = todo.priority;
+                            *input_deadline = todo.deadline.map(format_deadline).unwrap_or_default();
+                            *input_recurrence = todo.recurrence.as_ref().map(ToString::to_string).unwrap_or_default();
+                            *input_tags = format_tags(&todo.tags);
+                        }
+                    }
+                    KeyCode::Char('p') => {
+                        *input_mode = InputMode::SwitchingProject;
+                        let entries = picker_entries(todos, &view.created, true);
+                        let current = entries.iter().position(|entry| match entry {
+                            PickerEntry::AllProjects => view.project.is_none(),
+                            PickerEntry::Project(name, _) => view.project.as_ref() == Some(name),
+                            PickerEntry::NewProject => false,
+                        });
+                        view.picker.select(current.or(Some(0)));
+                    }
+                    KeyCode::Char('m') => {
+                        // サブタスクの上なら、その親のタスクを移す
+                        if let Some(path) = selected_path(todos, view, state) {
+                            *input_mode = InputMode::MovingTask(todos[path[0]].id.clone());
+                            view.picker.select(Some(0));
+                        }
+                    }
+                    KeyCode::Char('t') => {
+                        *input_mode = InputMode::FilteringTags;
+                        if tag_state.selected().is_none() {
+                            tag_state.select(Some(0));
+                        }
                     }
+                    KeyCode::Char('A') => {
+                        if let Some(todo) = selected_path(todos, view, state).and_then(|path| tree::get(todos, &path)) {
+                            *input_mode = InputMode::AddingSubtask(todo.id.clone(), false);
+                        }
+                    }
+                    // 畳んだ状態も保存する(保存しないと一覧が保存先と食い違い、他の ltd の変更を取り込めなくなる)
                     KeyCode::Char('l') => {
-                        if let Some(selected) = state.selected() {
-                            let (main_index, sub_index) = get_main_and_sub_index(filtered_todos, selected);
-                            if let Some(main_index) = main_index {
-                                if sub_index.is_none() {
-                                    filtered_todos[main_index].expanded = true;
-                                }
+                        if let Some(todo) = selected_path(todos, view, state).and_then(|path| tree::get_mut(todos, &path)) {
+                            if !todo.expanded {
+                                todo.expanded = true;
+                                save(todos, store, status_message);
                             }
                         }
                     }
+                    // 展開していれば畳み、畳んであれば親のタスクへカーソルを移す
                     KeyCode::Char('h') => {
-                        if let Some(selected) = state.selected() {
-                            let (main_index, sub_index) = get_main_and_sub_index(filtered_todos, selected);
-                            if let Some(main_index) = main_index {
-                                if sub_index.is_none() {
-                                    filtered_todos[main_index].expanded = false;
+                        if let Some(path) = selected_path(todos, view, state) {
+                            match tree::get_mut(todos, &path) {
+                                Some(todo) if todo.expanded && !todo.subtasks.is_empty() => {
+                                    todo.expanded = false;
+                                    save(todos, store, status_message);
                                 }
+                                _ if path.len() > 1 => select_path(todos, view, state, &path[..path.len() - 1]),
+                                _ => {}
                             }
                         }
                     }
                     KeyCode::Char('o') => {
-                        if let Some(selected) = state.selected() {
-                            let (main_index, sub_index) = get_main_and_sub_index(filtered_todos, selected);
-                            if let Some(main_index) = main_index {
-                                if sub_index.is_none() {
-                                    *input_mode = InputMode::ViewingDetails(main_index);
-                                    subtask_state.select(Some(0));
-                                }
-                            }
+                        if let Some(todo) = selected_path(todos, view, state).and_then(|path| tree::get(todos, &path)) {
+                            *input_mode = InputMode::ViewingDetails(todo.id.clone());
+                            subtask_state.select(Some(0));
                         }
                     }
                     KeyCode::Char('j') => {
-                        let mut index = state.selected().unwrap_or(0);
-                        index = move_cursor_down(filtered_todos, index);
-                        state.select(Some(index));
+                        let rows = visible_rows(todos, view).len();
+                        let index = state.selected().unwrap_or(0);
+                        state.select(Some((index + 1).min(rows.saturating_sub(1))));
                     }
                     KeyCode::Char('k') => {
-                        let mut index = state.selected().unwrap_or(0);
-                        index = move_cursor_up(filtered_todos, index);
-                        state.select(Some(index));
+                        let index = state.selected().unwrap_or(0);
+                        state.select(Some(index.saturating_sub(1)));
                     }
                     KeyCode::Char('s') => {
                         cycle_sort_mode(sort_mode);
-                        sort_todos(filtered_todos, sort_mode.clone());
+                        history.reorder(todos, "Sort", |todos| view.sort(todos, sort_mode.clone()));
+                        save_view(todos, store, view, sort_mode, status_message);
                     }
                     KeyCode::Enter => {
-                        if let Some(selected) = state.selected() {
-                            let (main_index, sub_index) = get_main_and_sub_index(filtered_todos, selected);
-                            if let Some(main_index) = main_index {
-                                if let Some(sub_index) = sub_index {
-                                    filtered_todos[main_index].subtasks[sub_index].done = !filtered_todos[main_index].subtasks[sub_index].done;
-                                } else {
-                                    filtered_todos[main_index].done = !filtered_todos[main_index].done;
+                        if let Some(path) = selected_path(todos, view, state) {
+                            let next = history.update(todos, &path, "Toggle", |todo| todo.toggle_done(Utc::now())).flatten();
+                            save(todos, store, status_message);
+                            if let Some(next) = next {
+                                if status_message.is_empty() {
+                                    *status_message = format!("Next: {}", format_deadline(next));
                                 }
-                                save_todos(todos);
                             }
                         }
                     }
@@ -174,11 +206,122 @@ pub fn handle_input(
                 }
             }
         }
-        InputMode::AddingTitle => match key.code {
-            KeyCode::Enter => {
-                if !input_title.is_empty() {
-                    *input_mode = InputMode::AddingContent;
+        // タグのサイドバー。選んだタグで一覧を絞り込む
+        InputMode::FilteringTags => {
+            let tags = all_tags(todos.iter().filter(|todo| view.in_project(todo)));
+            let selected = tag_state.selected().unwrap_or(0).min(tags.len().saturating_sub(1));
+            match key.code {
+                KeyCode::Char('j') | KeyCode::Down => {
+                    tag_state.select(Some((selected + 1).min(tags.len().saturating_sub(1))));
+                }
+                KeyCode::Char('k') | KeyCode::Up => {
+                    tag_state.select(Some(selected.saturating_sub(1)));
+                }
+                KeyCode::Char(' ') | KeyCode::Enter => {
+                    if let Some((tag, _)) = tags.get(selected) {
+                        view.tags.toggle(tag);
+                        state.select(Some(0));
+                    }
+                }
+                KeyCode::Char('m') => {
+                    view.tags.toggle_mode();
+                    state.select(Some(0));
+                }
+                KeyCode::Char('c') => {
+                    view.tags.selected.clear();
+                    state.select(Some(0));
+                }
+                KeyCode::Esc | KeyCode::Char('t') | KeyCode::Char('q') => {
+                    *input_mode = InputMode::Normal;
+                }
+                _ => {}
+            }
+        }
+        // プロジェクトの切り替えと、タスクを別のプロジェクトへ移すときの一覧
+        InputMode::SwitchingProject | InputMode::MovingTask(_) => {
+            let moving = match input_mode {
+                InputMode::MovingTask(id) => Some(id.clone()),
+                _ => None,
+            };
+            let entries = picker_entries(todos, &view.created, moving.is_none());
+            let selected = view.picker.selected().unwrap_or(0).min(entries.len() - 1);
+            match key.code {
+                KeyCode::Char('j') | KeyCode::Down => {
+                    view.picker.select(Some((selected + 1).min(entries.len() - 1)));
+                }
+                KeyCode::Char('k') | KeyCode::Up => {
+                    view.picker.select(Some(selected.saturating_sub(1)));
+                }
+                KeyCode::Char('n') => {
+                    view.new_project.clear();
+                    *input_mode = InputMode::NamingProject(moving);
+                }
+                KeyCode::Enter => match &entries[selected] {
+                    PickerEntry::NewProject => {
+                        view.new_project.clear();
+                        *input_mode = InputMode::NamingProject(moving);
+                    }
+                    PickerEntry::AllProjects => {
+                        view.switch(None, sort_mode);
+                        save_view(todos, store, view, sort_mode, status_message);
+                        state.select(Some(0));
+                        *input_mode = InputMode::Normal;
+                    }
+                    PickerEntry::Project(name, _) => {
+                        let name = name.clone();
+                        match moving {
+                            Some(id) => move_to_project(todos, &id, &name, store, status_message, history),
+                            None => {
+                                view.switch(Some(name), sort_mode);
+                                save_view(todos, store, view, sort_mode, status_message);
+                                state.select(Some(0));
+                            }
+                        }
+                        *input_mode = InputMode::Normal;
+                    }
+                },
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    *input_mode = InputMode::Normal;
+                }
+                _ => {}
+            }
+        }
+        InputMode::NamingProject(moving) => match key.code {
+            KeyCode::Enter if !view.new_project.trim().is_empty() => {
+                let name = view.new_project.trim().to_string();
+                match moving.take() {
+                    Some(id) => {
+                        if !view.created.contains(&name) {
+                            view.created.push(name.clone());
+                        }
+                        store.set_projects(view.settings(todos, sort_mode));
+                        move_to_project(todos, &id, &name, store, status_message, history);
+                    }
+                    None => {
+                        view.switch(Some(name), sort_mode);
+                        save_view(todos, store, view, sort_mode, status_message);
+                        state.select(Some(0));
+                    }
                 }
+                view.new_project.clear();
+                *input_mode = InputMode::Normal;
+            }
+            KeyCode::Char(c) => {
+                view.new_project.push(c);
+            }
+            KeyCode::Backspace => {
+                view.new_project.pop();
+            }
+            KeyCode::Esc => {
+                view.new_project.clear();
+                *input_mode = InputMode::Normal;
+            }
+            _ => {}
+        },
+        InputMode::AddingTitle => match key.code {
+            KeyCode::Enter if !input_title.is_empty() => {
+                take_title_tags(input_title, input_tags);
+                *input_mode = InputMode::AddingContent;
             }
             KeyCode::Char(c) => {
                 input_title.push(c);
@@ -189,12 +332,13 @@ pub fn handle_input(
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
                 input_title.clear();
+                input_tags.clear();
             }
             _ => {}
         },
         InputMode::AddingContent => match key.code {
             KeyCode::Enter => {
-                *input_mode = InputMode::AddingPriority;
+                *input_mode = InputMode::AddingTags;
             }
             KeyCode::Char(c) => {
                 input_content.push(c);
@@ -205,6 +349,25 @@ pub fn handle_input(
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
                 input_title.clear();
+                input_tags.clear();
+                input_content.clear();
+            }
+            _ => {}
+        },
+        InputMode::AddingTags => match key.code {
+            KeyCode::Enter => {
+                *input_mode = InputMode::AddingPriority;
+            }
+            KeyCode::Char(c) => {
+                input_tags.push(c);
+            }
+            KeyCode::Backspace => {
+                input_tags.pop();
+            }
+            KeyCode::Esc => {
+                *input_mode = InputMode::Normal;
+                input_title.clear();
+                input_tags.clear();
                 input_content.clear();
             }
             _ => {}
@@ -222,49 +385,79 @@ pub fn handle_input(
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
                 input_title.clear();
+                input_tags.clear();
                 input_content.clear();
                 input_deadline.clear();
+                input_recurrence.clear();
             }
             _ => {}
         },
         InputMode::AddingDeadline => match key.code {
-            KeyCode::Enter => {
-                if !input_title.is_empty() {
+            KeyCode::Enter => match parse_deadline(input_deadline, Local::now()) {
+                Ok(_) => *input_mode = InputMode::AddingRecurrence,
+                // 読めない期限は入力欄に残したまま知らせる
+                Err(e) => *status_message = e,
+            },
+            KeyCode::Char(c) => {
+                input_deadline.push(c);
+            }
+            KeyCode::Backspace => {
+                input_deadline.pop();
+            }
+            KeyCode::Esc => {
+                *input_mode = InputMode::Normal;
+                input_title.clear();
+                input_tags.clear();
+                input_content.clear();
+                input_deadline.clear();
+                input_recurrence.clear();
+            }
+            _ => {}
+        },
+        InputMode::AddingRecurrence => match key.code {
+            KeyCode::Enter if !input_title.is_empty() => match parse_schedule(input_deadline, input_recurrence) {
+                Ok((deadline, recurrence)) => {
                     add_task(
                         todos,
-                        filtered_todos,
                         input_title.clone(),
                         input_content.clone(),
-                        input_priority.to_str().to_string(),
-                        input_deadline.clone(),
+                        *input_priority,
+                        parse_tags(input_tags),
+                        deadline,
+                        recurrence,
+                        view.target_project(),
+                        history,
                     );
-                    save_todos(todos);
+                    save(todos, store, status_message);
                     *input_mode = InputMode::Normal;
                     input_title.clear();
+                    input_tags.clear();
                     input_content.clear();
                     input_deadline.clear();
+                    input_recurrence.clear();
                 }
-            }
+                Err(e) => *status_message = e,
+            },
             KeyCode::Char(c) => {
-                input_deadline.push(c);
+                input_recurrence.push(c);
             }
             KeyCode::Backspace => {
-                input_deadline.pop();
+                input_recurrence.pop();
             }
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
                 input_title.clear();
+                input_tags.clear();
                 input_content.clear();
                 input_deadline.clear();
+                input_recurrence.clear();
             }
             _ => {}
         },
-        InputMode::EditingTitle(index) => match key.code {
-            KeyCode::Enter => {
-                if !input_title.is_empty() {
-                    filtered_todos[*index].title = input_title.clone();
-                    *input_mode = InputMode::EditingContent(*index);
-                }
+        InputMode::EditingTitle(id) => match key.code {
+            KeyCode::Enter if !input_title.is_empty() => {
+                take_title_tags(input_title, input_tags);
+                *input_mode = InputMode::EditingContent(id.clone());
             }
             KeyCode::Char(c) => {
                 input_title.push(c);
@@ -275,15 +468,16 @@ pub fn handle_input(
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
                 input_title.clear();
+                input_tags.clear();
                 input_content.clear();
                 input_deadline.clear();
+                input_recurrence.clear();
             }
             _ => {}
         },
-        InputMode::EditingContent(index) => match key.code {
+        InputMode::EditingContent(id) => match key.code {
             KeyCode::Enter => {
-                filtered_todos[*index].content = input_content.clone();
-                *input_mode = InputMode::EditingPriority(*index);
+                *input_mode = InputMode::EditingTags(id.clone());
             }
             KeyCode::Char(c) => {
                 input_content.push(c);
@@ -294,14 +488,36 @@ pub fn handle_input(
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
                 input_title.clear();
+                input_tags.clear();
                 input_content.clear();
                 input_deadline.clear();
+                input_recurrence.clear();
             }
             _ => {}
         },
-        InputMode::EditingPriority(index) => match key.code {
+        InputMode::EditingTags(id) => match key.code {
             KeyCode::Enter => {
-                *input_mode = InputMode::EditingDeadline(*index);
+                *input_mode = InputMode::EditingPriority(id.clone());
+            }
+            KeyCode::Char(c) => {
+                input_tags.push(c);
+            }
+            KeyCode::Backspace => {
+                input_tags.pop();
+            }
+            KeyCode::Esc => {
+                *input_mode = InputMode::Normal;
+                input_title.clear();
+                input_tags.clear();
+                input_content.clear();
+                input_deadline.clear();
+                input_recurrence.clear();
+            }
+            _ => {}
+        },
+        InputMode::EditingPriority(id) => match key.code {
+            KeyCode::Enter => {
+                *input_mode = InputMode::EditingDeadline(id.clone());
             }
             KeyCode::Char('j') => {
                 *input_priority = input_priority.next();
@@ -312,34 +528,72 @@ pub fn handle_input(
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
                 input_title.clear();
+                input_tags.clear();
                 input_content.clear();
                 input_deadline.clear();
+                input_recurrence.clear();
             }
             _ => {}
         },
-        InputMode::EditingDeadline(index) => match key.code {
-            KeyCode::Enter => {
-                filtered_todos[*index].priority = input_priority.to_str().to_string();
-                filtered_todos[*index].deadline = input_deadline.clone();
-                filtered_todos[*index].date_time = chrono::Utc::now().to_rfc3339();
-                *todos = filtered_todos.clone();
-                save_todos(todos);
+        InputMode::EditingDeadline(id) => match key.code {
+            KeyCode::Enter => match parse_deadline(input_deadline, Local::now()) {
+                Ok(_) => *input_mode = InputMode::EditingRecurrence(id.clone()),
+                Err(e) => *status_message = e,
+            },
+            KeyCode::Char(c) => {
+                input_deadline.push(c);
+            }
+            KeyCode::Backspace => {
+                input_deadline.pop();
+            }
+            KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
                 input_title.clear();
+                input_tags.clear();
                 input_content.clear();
                 input_deadline.clear();
+                input_recurrence.clear();
             }
+            _ => {}
+        },
+        InputMode::EditingRecurrence(id) => match key.code {
+            KeyCode::Enter => match parse_schedule(input_deadline, input_recurrence) {
+                Ok((deadline, recurrence)) => {
+                    // 途中の段階では書き換えず、最後にまとめて1回の編集として履歴に残す
+                    if let Some(path) = tree::path_of(todos, id) {
+                        history.update(todos, &path, "Edit", |todo| {
+                            todo.title = input_title.clone();
+                            todo.content = input_content.clone();
+                            todo.tags = parse_tags(input_tags);
+                            todo.priority = *input_priority;
+                            todo.deadline = deadline;
+                            todo.recurrence = recurrence;
+                            todo.date_time = chrono::Utc::now().to_rfc3339();
+                        });
+                    }
+                    save(todos, store, status_message);
+                    *input_mode = InputMode::Normal;
+                    input_title.clear();
+                    input_tags.clear();
+                    input_content.clear();
+                    input_deadline.clear();
+                    input_recurrence.clear();
+                }
+                Err(e) => *status_message = e,
+            },
             KeyCode::Char(c) => {
-                input_deadline.push(c);
+                input_recurrence.push(c);
             }
             KeyCode::Backspace => {
-                input_deadline.pop();
+                input_recurrence.pop();
             }
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
                 input_title.clear();
+                input_tags.clear();
                 input_content.clear();
                 input_deadline.clear();
+                input_recurrence.clear();
             }
             _ => {}
         },
@@ -352,25 +606,32 @@ pub fn handle_input(
                 search_query.pop();
                 search_state.select(Some(0));
             }
+            // 一覧を検索の文字列で絞り込み、選んでいた候補にカーソルを合わせる
             KeyCode::Enter => {
-                *filtered_todos = search_todos(todos, search_query);
-                *input_mode = InputMode::Normal;
+                let results = search_results(todos, view, search_query);
+                let selected = search_state.selected().and_then(|selected| results.get(selected)).map(|hit| hit.index);
+                view.search = search_query.clone();
                 state.select(Some(0));
-                terminal.backend_mut().execute(Show)?;
+                if let Some(index) = selected {
+                    select_path(todos, view, state, &[index]);
+                }
+                *input_mode = InputMode::Normal;
+                effects.push(Effect::ShowCursor);
             }
             KeyCode::Esc => {
                 *input_mode = InputMode::Normal;
                 search_query.clear();
-                terminal.backend_mut().execute(Show)?;
+                effects.push(Effect::ShowCursor);
             }
-            KeyCode::Char('j') => {
+            KeyCode::Down => {
+                let results = search_results(todos, view, search_query).len();
                 if let Some(selected) = search_state.selected() {
-                    if selected < todos.len() - 1 {
+                    if selected + 1 < results {
                         search_state.select(Some(selected + 1));
                     }
                 }
             }
-            KeyCode::Char('k') => {
+            KeyCode::Up => {
                 if let Some(selected) = search_state.selected() {
                     if selected > 0 {
                         search_state.select(Some(selected - 1));
@@ -379,15 +640,16 @@ pub fn handle_input(
             }
             _ => {}
         },
-        InputMode::ViewingDetails(index) => {
+        InputMode::ViewingDetails(id) => {
+            let subtask_count = find_todo(todos, id).map_or(0, |todo| todo.subtasks.len());
             if key.code == KeyCode::Char('q') {
                 *input_mode = InputMode::Normal;
             } else if key.code == KeyCode::Char(' ') {
-                *input_mode = InputMode::AddingSubtask(*index);
+                *input_mode = InputMode::AddingSubtask(id.clone(), true);
             } else if key.code == KeyCode::Char('j') {
                 if let Some(selected) = subtask_state.selected() {
                     let new_index = selected.saturating_add(1);
-                    if new_index < filtered_todos[*index].subtasks.len() {
+                    if new_index < subtask_count {
                         subtask_state.select(Some(new_index));
                     }
                 } else {
@@ -399,30 +661,42 @@ pub fn handle_input(
                     subtask_state.select(Some(new_index));
                 }
             } else if key.code == KeyCode::Enter {
-                if let Some(selected) = subtask_state.selected() {
-                    let subtask = &mut filtered_todos[*index].subtasks[selected];
-                    subtask.done = !subtask.done;
-                    save_todos(filtered_todos);
+                if let (Some(mut path), Some(selected)) = (tree::path_of(todos, id), subtask_state.selected()) {
+                    path.push(selected);
+                    if history.update(todos, &path, "Toggle", |subtask| subtask.toggle_done(Utc::now())).is_some() {
+                        save(todos, store, status_message);
+                    }
+                }
+            } else if key.code == KeyCode::Char('o') {
+                // 選んだサブタスクの詳細へ(さらに下の段へも同じように潜れる)
+                let subtask = subtask_state
+                    .selected()
+                    .and_then(|selected| find_todo(todos, id)?.subtasks.get(selected));
+                if let Some(subtask) = subtask {
+                    *input_mode = InputMode::ViewingDetails(subtask.id.clone());
+                    subtask_state.select(Some(0));
+                }
+            } else if key.code == KeyCode::Char('h') {
+                if let Some(parent) = tree::path_of(todos, id).filter(|path| path.len() > 1) {
+                    if let Some(parent) = tree::get(todos, &parent[..parent.len() - 1]) {
+                        *input_mode = InputMode::ViewingDetails(parent.id.clone());
+                        subtask_state.select(Some(0));
+                    }
                 }
             }
         }
-        InputMode::ViewingSubtaskDetails(task_index, subtask_index) => {
-            if key.code == KeyCode::Char('q') {
-                *input_mode = InputMode::ViewingDetails(*task_index);
-            } else if key.code == KeyCode::Enter {
-                let subtask = &mut filtered_todos[*task_index].subtasks[*subtask_index];
-                subtask.done = !subtask.done;
-                save_todos(filtered_todos);
-            }
-        }
-        InputMode::AddingSubtask(index) => match key.code {
-            KeyCode::Enter => {
-                if !input_title.is_empty() {
-                    filtered_todos[*index].add_subtask(input_title.clone());
-                    save_todos(filtered_todos);
-                    *input_mode = InputMode::ViewingDetails(*index);
-                    input_title.clear();
+        InputMode::AddingSubtask(id, from_details) => match key.code {
+            KeyCode::Enter if !input_title.is_empty() => {
+                if let Some(path) = tree::path_of(todos, id) {
+                    history.update(todos, &path, "Add subtask to", |todo| {
+                        todo.add_subtask(input_title.clone());
+                        todo.expanded = true;
+                    });
                 }
+                save(todos, store, status_message);
+                *input_mode = if *from_details { InputMode::ViewingDetails(id.clone()) } else { InputMode::Normal };
+                input_title.clear();
+                input_tags.clear();
             }
             KeyCode::Char(c) => {
                 input_title.push(c);
@@ -431,108 +705,139 @@ pub fn handle_input(
                 input_title.pop();
             }
             KeyCode::Esc => {
-                *input_mode = InputMode::ViewingDetails(*index);
+                *input_mode = if *from_details { InputMode::ViewingDetails(id.clone()) } else { InputMode::Normal };
                 input_title.clear();
+                input_tags.clear();
             }
             _ => {}
         },
-    }
-    Ok(())
-}
-
-fn move_cursor_down(todos: &Vec<Todo>, index: usize) -> usize {
-    let mut current_index = 0;
-    let mut target_index = index;
-    for (main_index, todo) in todos.iter().enumerate() {
-        if current_index == index {
-            target_index = current_index + 1;
-            break;
+        InputMode::LoadFailed => match key.code {
+            KeyCode::Char('v') => {
+                *input_mode = InputMode::RecoveryView;
+            }
+            KeyCode::Char('b') => match restore_newest_backup(store.path()) {
+                Ok(Some(_)) => match store.load() {
+                    Ok(loaded) => {
+                        *todos = loaded;
+                        *load_error = None;
+                        history.clear();
+                        *input_mode = InputMode::Normal;
+                        state.select(Some(0));
+                    }
+                    Err(e) => *status_message = format!("Backup restored but unreadable: {}", e),
+                },
+                Ok(None) => *status_message = String::from("No readable backup found"),
+                Err(e) => *status_message = format!("Failed to restore backup: {}", e),
+            },
+            KeyCode::Char('n') => match set_aside(store.path()) {
+                Ok(aside) => {
+                    // 退避したのでファイルは無く、空の一覧から始まる
+                    *todos = store.load().unwrap_or_default();
+                    *load_error = None;
+                    history.clear();
+                    *input_mode = InputMode::Normal;
+                    *status_message = format!("Moved broken file to {}", aside.display());
+                }
+                Err(e) => *status_message = format!("Failed to move broken file: {}", e),
+            },
+            KeyCode::Char('q') => {
+                return vec![Effect::Quit(EXIT_LOAD_FAILED)];
+            }
+            _ => {}
+        },
+        InputMode::RecoveryView => {
+            if key.code == KeyCode::Char('q') || key.code == KeyCode::Esc {
+                *input_mode = InputMode::LoadFailed;
+            }
         }
-        current_index += 1;
-        if todo.expanded {
-            for (sub_index, _subtask) in todo.subtasks.iter().enumerate() {
-                if current_index == index {
-                    target_index = current_index + 1;
-                    break;
+        InputMode::ResolvingConflict => {
+            let keep_mine = match key.code {
+                KeyCode::Char('m') => Some(true),
+                KeyCode::Char('t') => Some(false),
+                _ => None,
+            };
+            if let Some(keep_mine) = keep_mine {
+                match store.resolve_conflict(keep_mine, todos) {
+                    Ok(Some(_)) => {
+                        state.select(Some(0));
+                    }
+                    Ok(None) => {}
+                    Err(e) => *status_message = format!("Failed to save: {}", e),
+                }
+                if store.current_conflict().is_none() {
+                    *input_mode = InputMode::Normal;
                 }
-                current_index += 1;
             }
         }
     }
-    target_index.min(total_items(todos) - 1)
+    if store.current_conflict().is_some() {
+        *input_mode = InputMode::ResolvingConflict;
+    }
+    effects
 }
 
-fn move_cursor_up(todos: &Vec<Todo>, index: usize) -> usize {
-    let mut current_index = 0;
-    let mut target_index = index;
-    for (main_index, todo) in todos.iter().enumerate() {
-        if current_index == index {
-            if current_index > 0 {
-                target_index = current_index - 1;
-            }
-            break;
-        }
-        current_index += 1;
-        if todo.expanded {
-            for (sub_index, _subtask) in todo.subtasks.iter().enumerate() {
-                if current_index == index {
-                    if current_index > 0 {
-                        target_index = current_index - 1;
-                    }
-                    break;
-                }
-                current_index += 1;
-            }
+// タイトルに書いた #tag をタグの入力欄へ移す(タグしか無いタイトルはそのまま)
+fn take_title_tags(input_title: &mut String, input_tags: &mut String) {
+    let (title, tags) = extract_tags(input_title);
+    if tags.is_empty() || title.is_empty() {
+        return;
+    }
+    // 続けてタグを打てるように末尾に空白を入れておく
+    *input_tags = format!("{} ", format_tags(&parse_tags(&format!("{} {}", input_tags, format_tags(&tags)))));
+    *input_title = title;
+}
+
+fn move_to_project(todos: &mut Vec<Todo>, id: &str, project: &str, store: &mut Store, status_message: &mut String, history: &mut History) {
+    if let Some(index) = todos.iter().position(|todo| todo.id == id) {
+        history.update(todos, &[index], "Move", |todo| todo.project = project.to_string());
+        save(todos, store, status_message);
+        if status_message.is_empty() {
+            *status_message = format!("Moved to {}", project);
         }
     }
-    target_index
 }
 
-fn toggle_done(todos: &mut Vec<Todo>, index: usize) {
-    let mut current_index = 0;
-    for todo in todos {
-        if current_index == index {
-            todo.done = !todo.done;
-            break;
+// 編集中の一覧を保存する。他の ltd の変更を取り込んだときは表示にも反映する
+fn save(todos: &mut Vec<Todo>, store: &mut Store, status_message: &mut String) {
+    match store.save(todos) {
+        Ok(SaveOutcome::Saved) => {}
+        Ok(SaveOutcome::Merged) => {
+            *status_message = String::from("Merged changes from another ltd");
         }
-        current_index += 1;
-        if todo.expanded {
-            for subtask in &mut todo.subtasks {
-                if current_index == index {
-                    subtask.done = !subtask.done;
-                    break;
-                }
-                current_index += 1;
-            }
+        Ok(SaveOutcome::Conflicts) => {
+            *status_message = String::from("Another ltd edited the same task");
         }
+        Err(e) => *status_message = format!("Failed to save: {}", e),
     }
 }
 
-fn total_items(todos: &Vec<Todo>) -> usize {
-    todos.iter().map(|todo| {
-        if todo.expanded {
-            1 + todo.subtasks.len()
-        } else {
-            1
-        }
-    }).sum()
+// プロジェクトの一覧や並び順を変えたときは、その設定もタスクと一緒に保存する
+fn save_view(todos: &mut Vec<Todo>, store: &mut Store, view: &ListView, sort_mode: &SortMode, status_message: &mut String) {
+    store.set_projects(view.settings(todos, sort_mode));
+    save(todos, store, status_message);
 }
 
-fn get_main_and_sub_index(todos: &Vec<Todo>, index: usize) -> (Option<usize>, Option<usize>) {
-    let mut current_index = 0;
-    for (main_index, todo) in todos.iter().enumerate() {
-        if current_index == index {
-            return (Some(main_index), None);
-        }
-        current_index += 1;
-        if todo.expanded {
-            for (sub_index, _subtask) in todo.subtasks.iter().enumerate() {
-                if current_index == index {
-                    return (Some(main_index), Some(sub_index));
-                }
-                current_index += 1;
-            }
+// カーソルのある行のタスクの木の中の位置
+fn selected_path(todos: &[Todo], view: &ListView, state: &ListState) -> Option<TreePath> {
+    visible_rows(todos, view).into_iter().nth(state.selected()?).map(|row| row.path)
+}
+
+// path のタスクの行にカーソルを置く
+fn select_path(todos: &[Todo], view: &ListView, state: &mut ListState, path: &[usize]) {
+    if let Some(index) = visible_rows(todos, view).iter().position(|row| row.path == path) {
+        state.select(Some(index));
+    }
+}
+
+// 取り消し / やり直しで変わったタスクにカーソルを合わせる(畳まれていたら親を開く)
+fn focus_task(todos: &mut [Todo], view: &ListView, state: &mut ListState, id: Option<String>) {
+    let Some(path) = id.and_then(|id| tree::path_of(todos, &id)) else {
+        return;
+    };
+    for depth in 1..path.len() {
+        if let Some(ancestor) = tree::get_mut(todos, &path[..depth]) {
+            ancestor.expanded = true;
         }
     }
-    (None, None)
+    select_path(todos, view, state, &path);
 }
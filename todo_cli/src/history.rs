@@ -0,0 +1,236 @@
+use crate::todo::{write_atomic, Todo};
+use crate::tree::{self, TreePath};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// 一覧への変更1回分。戻すときは逆向きに当てる
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    // path の位置にタスクを入れた
+    Insert { path: TreePath, todo: Todo },
+    // path の位置のタスクを消した(下の段ごと)
+    Remove { path: TreePath, todo: Todo },
+    // タスクを書き換えた。位置ではなく ID で探す
+    Update { before: Box<Todo>, after: Box<Todo> },
+    // 親のタスクの並び順を変えた(ID の並び)
+    Reorder { before: Vec<String>, after: Vec<String> },
+    // まとめて1回で戻す変更(import など)。戻すときは後ろから
+    Batch { changes: Vec<Change> },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Command {
+    pub label: String,
+    pub change: Change,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct HistoryFile {
+    undo: VecDeque<Command>,
+    redo: Vec<Command>,
+}
+
+// 取り消し / やり直しの履歴。file があれば次に起動したときも続きから戻せる
+pub struct History {
+    undo: VecDeque<Command>,
+    redo: Vec<Command>,
+    limit: usize,
+    file: Option<PathBuf>,
+}
+
+// todos.json の履歴は todos.json.history に置く
+pub fn history_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".history");
+    db_path.with_file_name(name)
+}
+
+impl History {
+    // 読めない履歴ファイルは捨てて空から始める
+    pub fn new(limit: usize, file: Option<PathBuf>) -> Self {
+        let saved: HistoryFile = file
+            .as_ref()
+            .and_then(|file| fs::read(file).ok())
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        let mut history = History {
+            undo: saved.undo,
+            redo: saved.redo,
+            limit,
+            file,
+        };
+        history.trim();
+        history
+    }
+
+    // action は「Add」のような操作の名前。履歴にはタスクのタイトルを付けて残す
+    pub fn insert(&mut self, todos: &mut Vec<Todo>, path: &[usize], todo: Todo, action: &str) {
+        let path = path.to_vec();
+        if tree::insert(todos, &path, todo.clone()).is_none() {
+            self.record(label(action, &todo), Change::Insert { path, todo });
+        }
+    }
+
+    // 親のタスクをまとめて末尾に足す。1回の取り消しで全部消える
+    pub fn append(&mut self, todos: &mut Vec<Todo>, added: Vec<Todo>, label: String) {
+        let changes: Vec<Change> = added
+            .into_iter()
+            .map(|todo| {
+                let path = vec![todos.len()];
+                todos.push(todo.clone());
+                Change::Insert { path, todo }
+            })
+            .collect();
+        if !changes.is_empty() {
+            self.record(label, Change::Batch { changes });
+        }
+    }
+
+    pub fn remove(&mut self, todos: &mut Vec<Todo>, path: &[usize], action: &str) -> Option<Todo> {
+        let todo = tree::remove(todos, path)?;
+        self.record(
+            label(action, &todo),
+            Change::Remove {
+                path: path.to_vec(),
+                todo: todo.clone(),
+            },
+        );
+        Some(todo)
+    }
+
+    // path のタスクを change で書き換える。何も変わらなければ履歴に残さない
+    pub fn update<T>(&mut self, todos: &mut [Todo], path: &[usize], action: &str, change: impl FnOnce(&mut Todo) -> T) -> Option<T> {
+        let todo = tree::get_mut(todos, path)?;
+        let before = todo.clone();
+        let result = change(todo);
+        if *todo != before {
+            let after = Box::new(todo.clone());
+            self.record(label(action, &before), Change::Update { before: Box::new(before), after });
+        }
+        Some(result)
+    }
+
+    // 親のタスクを並べ替える
+    pub fn reorder(&mut self, todos: &mut [Todo], action: &str, sort: impl FnOnce(&mut [Todo])) {
+        let before = ids(todos);
+        sort(todos);
+        let after = ids(todos);
+        if before != after {
+            self.record(action.to_string(), Change::Reorder { before, after });
+        }
+    }
+
+    // 直前の変更を戻す。戻したコマンドの名前と、カーソルを合わせるタスクの ID を返す
+    pub fn undo(&mut self, todos: &mut Vec<Todo>) -> Option<(String, Option<String>)> {
+        let command = self.undo.pop_back()?;
+        let focus = revert(todos, &command.change);
+        let label = command.label.clone();
+        self.redo.push(command);
+        self.persist();
+        Some((label, focus))
+    }
+
+    pub fn redo(&mut self, todos: &mut Vec<Todo>) -> Option<(String, Option<String>)> {
+        let command = self.redo.pop()?;
+        let focus = apply(todos, &command.change);
+        let label = command.label.clone();
+        self.undo.push_back(command);
+        self.trim();
+        self.persist();
+        Some((label, focus))
+    }
+
+    // 一覧を丸ごと読み直したときは、古い位置を指す履歴を捨てる
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.persist();
+    }
+
+    fn record(&mut self, label: String, change: Change) {
+        self.undo.push_back(Command { label, change });
+        self.redo.clear();
+        self.trim();
+        self.persist();
+    }
+
+    fn trim(&mut self) {
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+        self.redo.truncate(self.limit);
+    }
+
+    // 履歴の保存に失敗しても一覧の操作は続けられるので、エラーは無視する
+    fn persist(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let saved = HistoryFile {
+            undo: self.undo.clone(),
+            redo: self.redo.clone(),
+        };
+        if let Ok(data) = serde_json::to_vec(&saved) {
+            let _ = write_atomic(file, &data);
+        }
+    }
+}
+
+fn label(action: &str, todo: &Todo) -> String {
+    format!("{} \"{}\"", action, todo.title)
+}
+
+fn ids(todos: &[Todo]) -> Vec<String> {
+    todos.iter().map(|todo| todo.id.clone()).collect()
+}
+
+fn apply(todos: &mut Vec<Todo>, change: &Change) -> Option<String> {
+    match change {
+        Change::Insert { path, todo } => {
+            if let Some(todo) = tree::insert(todos, path, todo.clone()) {
+                todos.push(todo);
+            }
+            Some(todo.id.clone())
+        }
+        Change::Remove { todo, .. } => {
+            let path = tree::path_of(todos, &todo.id)?;
+            tree::remove(todos, &path);
+            None
+        }
+        Change::Update { after, .. } => replace(todos, after),
+        Change::Reorder { after, .. } => {
+            reorder(todos, after);
+            None
+        }
+        Change::Batch { changes } => changes.iter().map(|change| apply(todos, change)).fold(None, Option::or),
+    }
+}
+
+fn revert(todos: &mut Vec<Todo>, change: &Change) -> Option<String> {
+    match change {
+        Change::Insert { path, todo } => apply(todos, &Change::Remove { path: path.clone(), todo: todo.clone() }),
+        Change::Remove { path, todo } => apply(todos, &Change::Insert { path: path.clone(), todo: todo.clone() }),
+        Change::Update { before, .. } => replace(todos, before),
+        Change::Reorder { before, .. } => {
+            reorder(todos, before);
+            None
+        }
+        Change::Batch { changes } => changes.iter().rev().map(|change| revert(todos, change)).fold(None, Option::or),
+    }
+}
+
+// 同じ ID のタスクを todo に差し替える。他の ltd で消されていたら何もしない
+fn replace(todos: &mut [Todo], todo: &Todo) -> Option<String> {
+    let path = tree::path_of(todos, &todo.id)?;
+    *tree::get_mut(todos, &path)? = todo.clone();
+    Some(todo.id.clone())
+}
+
+// order に無いタスク(後から増えたもの)は今の順のまま後ろに回す
+fn reorder(todos: &mut [Todo], order: &[String]) {
+    todos.sort_by_key(|todo| order.iter().position(|id| *id == todo.id).unwrap_or(order.len()));
+}
@@ -0,0 +1,509 @@
+use crate::deadline::{end_of_day, to_utc};
+use crate::priority::Priority;
+use crate::recurrence::Recurrence;
+use crate::tags::normalize_tag;
+use crate::todo::Todo;
+use chrono::prelude::*;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use chrono_tz::Tz;
+use std::collections::{BTreeSet, HashMap};
+use ulid::Ulid;
+
+// iCalendar(RFC 5545)の VTODO との変換
+// 日時は UTC(末尾に Z)で書き出す。時刻を書かなかった期限(日の終わり)は日付だけの DUE にする
+// 読み込むときは Z 付きの UTC、TZID 付き(IANA のタイムゾーン名)、どちらも無い日時(ローカル時刻)、日付だけ、を読める
+
+const UTC_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const LOCAL_FORMAT: &str = "%Y%m%dT%H%M%S";
+const DATE_FORMAT: &str = "%Y%m%d";
+// 1行の長さ(改行を除いたバイト数)。これより長い行は折り返す
+const LINE_LIMIT: usize = 75;
+// ltd にしか無い項目。他のアプリは読み飛ばす
+const PROJECT_PROPERTY: &str = "X-LTD-PROJECT";
+
+// 1 が一番高く 9 が一番低い(0 は指定なし)
+fn priority_number(priority: Priority) -> u8 {
+    match priority {
+        Priority::Urgent => 1,
+        Priority::High => 3,
+        Priority::Medium => 5,
+        Priority::Low => 7,
+        Priority::None => 0,
+    }
+}
+
+fn number_priority(number: u8) -> Priority {
+    match number {
+        1 => Priority::Urgent,
+        2..=4 => Priority::High,
+        5 => Priority::Medium,
+        6..=9 => Priority::Low,
+        _ => Priority::None,
+    }
+}
+
+const WEEKDAYS: [(Weekday, &str); 7] = [
+    (Weekday::Mon, "MO"),
+    (Weekday::Tue, "TU"),
+    (Weekday::Wed, "WE"),
+    (Weekday::Thu, "TH"),
+    (Weekday::Fri, "FR"),
+    (Weekday::Sat, "SA"),
+    (Weekday::Sun, "SU"),
+];
+
+fn rrule(recurrence: &Recurrence) -> Option<String> {
+    match recurrence {
+        Recurrence::Daily => Some("FREQ=DAILY".to_string()),
+        Recurrence::Weekly { days } => {
+            let days: Vec<&str> = days.iter().filter_map(|day| WEEKDAYS.iter().find(|(weekday, _)| weekday == day)).map(|(_, code)| *code).collect();
+            Some(format!("FREQ=WEEKLY;BYDAY={}", days.join(",")))
+        }
+        Recurrence::Monthly { day } => Some(format!("FREQ=MONTHLY;BYMONTHDAY={}", day)),
+        // 完了した日から数える繰り返しは iCalendar に無い
+        Recurrence::AfterCompletion { .. } => None,
+    }
+}
+
+// DUE や DTSTART の「:」から後ろ。日の終わりなら日付だけにする
+fn date_time_value(deadline: DateTime<Utc>) -> String {
+    let local = deadline.with_timezone(&Local);
+    if local.time() == end_of_day() {
+        format!(";VALUE=DATE:{}", local.format(DATE_FORMAT))
+    } else {
+        format!(":{}", deadline.format(UTC_FORMAT))
+    }
+}
+
+// 戻り値は書き出した文字列と、iCalendar に書けなかったものの一覧
+pub fn export(todos: &[Todo]) -> (String, Vec<String>) {
+    fn push(todo: &Todo, parent: Option<&str>, stamp: &str, lines: &mut Vec<String>, warnings: &mut Vec<String>) {
+        lines.push("BEGIN:VTODO".to_string());
+        lines.push(format!("UID:{}", todo.id));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        if let Ok(created) = DateTime::parse_from_rfc3339(&todo.date_time) {
+            lines.push(format!("CREATED:{}", created.with_timezone(&Utc).format(UTC_FORMAT)));
+        }
+        lines.push(format!("SUMMARY:{}", escape(&todo.title)));
+        if !todo.content.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", escape(&todo.content)));
+        }
+        if let Some(deadline) = todo.deadline {
+            lines.push(format!("DUE{}", date_time_value(deadline)));
+        }
+        if todo.priority != Priority::None {
+            lines.push(format!("PRIORITY:{}", priority_number(todo.priority)));
+        }
+        lines.push(format!("STATUS:{}", if todo.done { "COMPLETED" } else { "NEEDS-ACTION" }));
+        if !todo.subtasks.is_empty() {
+            lines.push(format!("PERCENT-COMPLETE:{:.0}", todo.completion_rate()));
+        }
+        if !todo.tags.is_empty() {
+            let tags: Vec<String> = todo.tags.iter().map(|tag| escape(tag)).collect();
+            lines.push(format!("CATEGORIES:{}", tags.join(",")));
+        }
+        if !todo.project.is_empty() {
+            lines.push(format!("{}:{}", PROJECT_PROPERTY, escape(&todo.project)));
+        }
+        // RRULE は DTSTART から数えるので、期限と同じ日時を DTSTART にする。期限が無ければ書けない
+        if let Some(recurrence) = &todo.recurrence {
+            match (rrule(recurrence), todo.deadline) {
+                (Some(rule), Some(deadline)) => {
+                    lines.push(format!("DTSTART{}", date_time_value(deadline)));
+                    lines.push(format!("RRULE:{}", rule));
+                }
+                (Some(_), None) => warnings.push(format!("\"{}\": repeat rule \"{}\" is not exported without a deadline", todo.title, recurrence)),
+                (None, _) => warnings.push(format!("\"{}\": repeat rule \"{}\" is not exported", todo.title, recurrence)),
+            }
+        }
+        if let Some(parent) = parent {
+            lines.push(format!("RELATED-TO;RELTYPE=PARENT:{}", parent));
+        }
+        lines.push("END:VTODO".to_string());
+        for subtask in &todo.subtasks {
+            push(subtask, Some(&todo.id), stamp, lines, warnings);
+        }
+    }
+
+    let stamp = Utc::now().format(UTC_FORMAT).to_string();
+    let mut lines = vec!["BEGIN:VCALENDAR".to_string(), "VERSION:2.0".to_string(), "PRODID:-//ltd//Lazy Todo//EN".to_string()];
+    let mut warnings = vec![];
+    for todo in todos {
+        push(todo, None, &stamp, &mut lines, &mut warnings);
+    }
+    lines.push("END:VCALENDAR".to_string());
+    let mut text = String::new();
+    for line in lines {
+        text.push_str(&fold(&line));
+        text.push_str("\r\n");
+    }
+    (text, warnings)
+}
+
+// 1つの項目。name;param=value:value
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+// 読み込んだ VTODO。uid と parent は UID と RELATED-TO の値
+struct Entry {
+    todo: Todo,
+    uid: Option<String>,
+    parent: Option<String>,
+}
+
+// 戻り値は読み込んだタスクと、ltd に入れられなかったものの一覧
+pub fn import(text: &str) -> (Vec<Todo>, Vec<String>) {
+    let mut warnings = vec![];
+    let mut entries: Vec<Entry> = vec![];
+    // 読み飛ばした VEVENT などの数と、VTODO の中の知らない項目の名前
+    // VTIMEZONE は TZID の名前で IANA のタイムゾーンを引くので読まなくてよい
+    let mut skipped: HashMap<String, usize> = HashMap::new();
+    let mut ignored: BTreeSet<String> = BTreeSet::new();
+    let mut completion_dates = 0;
+    let mut components: Vec<String> = vec![];
+    let mut current: Option<Vec<Property>> = None;
+
+    for line in unfold(text) {
+        let Some(property) = parse_line(&line) else {
+            continue;
+        };
+        match property.name.as_str() {
+            "BEGIN" => {
+                let component = property.value.to_uppercase();
+                if component == "VTODO" && components.last().is_some_and(|parent| parent == "VCALENDAR") {
+                    current = Some(vec![]);
+                } else if !["VCALENDAR", "VTIMEZONE"].contains(&component.as_str()) && components.len() == 1 {
+                    *skipped.entry(component.clone()).or_default() += 1;
+                }
+                components.push(component);
+            }
+            "END" => {
+                let ended = components.pop();
+                let properties = if ended.as_deref() == Some("VTODO") { current.take() } else { None };
+                if let Some(entry) = properties.and_then(|properties| read_todo(&properties, &mut warnings, &mut ignored, &mut completion_dates)) {
+                    entries.push(entry);
+                }
+            }
+            // VTODO の中の VALARM などは読まない
+            _ if components.last().is_some_and(|component| component == "VTODO") => {
+                if let Some(properties) = &mut current {
+                    properties.push(property);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // RELATED-TO で親子をつなぐ。親が見つからなければ親のタスクとして残す
+    let uids: HashMap<String, usize> = entries.iter().enumerate().filter_map(|(index, entry)| Some((entry.uid.clone()?, index))).collect();
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut roots = vec![];
+    for (index, entry) in entries.iter().enumerate() {
+        match entry.parent.as_ref().map(|parent| uids.get(parent).copied()) {
+            Some(Some(parent)) if !creates_cycle(&entries, &uids, index, parent) => children.entry(parent).or_default().push(index),
+            Some(_) => {
+                warnings.push(format!("parent of \"{}\" not found; imported it as a task of its own", entry.todo.title));
+                roots.push(index);
+            }
+            None => roots.push(index),
+        }
+    }
+    let mut slots: Vec<Option<Todo>> = entries.into_iter().map(|entry| Some(entry.todo)).collect();
+    fn build(index: usize, slots: &mut [Option<Todo>], children: &HashMap<usize, Vec<usize>>) -> Option<Todo> {
+        let mut todo = slots[index].take()?;
+        for &child in children.get(&index).into_iter().flatten() {
+            if let Some(mut subtask) = build(child, slots, children) {
+                // サブタスクは親のプロジェクトに入る
+                subtask.project.clear();
+                todo.subtasks.push(subtask);
+                todo.expanded = true;
+            }
+        }
+        Some(todo)
+    }
+    let todos = roots.into_iter().filter_map(|index| build(index, &mut slots, &children)).collect();
+
+    let mut skipped: Vec<(String, usize)> = skipped.into_iter().collect();
+    skipped.sort();
+    for (component, count) in skipped {
+        warnings.push(format!("skipped {} {} (only VTODO is read)", count, component));
+    }
+    if !ignored.is_empty() {
+        warnings.push(format!("ltd has no field for {}; not kept", ignored.into_iter().collect::<Vec<_>>().join(", ")));
+    }
+    if completion_dates > 0 {
+        warnings.push(format!(
+            "{} completion date{} not kept (ltd only records whether a task is done)",
+            completion_dates,
+            if completion_dates == 1 { " was" } else { "s were" }
+        ));
+    }
+    (todos, warnings)
+}
+
+fn read_todo(properties: &[Property], warnings: &mut Vec<String>, ignored: &mut BTreeSet<String>, completion_dates: &mut usize) -> Option<Entry> {
+    let mut todo = Todo::new(String::new(), String::new(), Priority::None, None);
+    let (mut uid, mut parent, mut rule, mut start) = (None, None, None, None);
+    for property in properties {
+        match property.name.as_str() {
+            "SUMMARY" => todo.title = unescape(&property.value).trim().to_string(),
+            "DESCRIPTION" => todo.content = unescape(&property.value),
+            "UID" => {
+                if Ulid::from_string(&property.value).is_ok() {
+                    todo.id = property.value.clone();
+                }
+                uid = Some(property.value.clone());
+            }
+            "DUE" => match parse_date_time(property, end_of_day(), warnings) {
+                Some(deadline) => todo.deadline = Some(deadline),
+                None => warnings.push(format!("can't read DUE:{}", property.value)),
+            },
+            "CREATED" => {
+                if let Some(created) = parse_date_time(property, NaiveTime::MIN, warnings) {
+                    todo.date_time = created.to_rfc3339();
+                }
+            }
+            "PRIORITY" => todo.priority = property.value.trim().parse().map_or(Priority::None, number_priority),
+            "STATUS" => match property.value.to_uppercase().as_str() {
+                "COMPLETED" => todo.done = true,
+                "CANCELLED" => {
+                    todo.done = true;
+                    warnings.push("a cancelled task was read as done".to_string());
+                }
+                _ => {}
+            },
+            "CATEGORIES" => {
+                for tag in split_list(&property.value).iter().filter_map(|tag| normalize_tag(tag)) {
+                    if !todo.tags.contains(&tag) {
+                        todo.tags.push(tag);
+                    }
+                }
+            }
+            PROJECT_PROPERTY => todo.project = unescape(&property.value),
+            "RELATED-TO" => match property.param("RELTYPE").map(str::to_uppercase).as_deref() {
+                None | Some("PARENT") => parent = Some(property.value.clone()),
+                Some(reltype) => {
+                    ignored.insert(format!("RELATED-TO;RELTYPE={}", reltype));
+                }
+            },
+            "RRULE" => rule = Some(property.value.clone()),
+            "DTSTART" => start = Some(property),
+            "COMPLETED" => *completion_dates += 1,
+            // 書き出すときに作り直すもの
+            "DTSTAMP" | "LAST-MODIFIED" | "SEQUENCE" | "PERCENT-COMPLETE" => {}
+            name if name.starts_with("X-") => {}
+            name => {
+                ignored.insert(name.to_string());
+            }
+        }
+    }
+    if todo.title.is_empty() {
+        warnings.push("skipped a VTODO without SUMMARY".to_string());
+        return None;
+    }
+    // 期限と同じ DTSTART は ltd が繰り返しのために書いたもの。違えば開始日は読めないと知らせる
+    if let Some(start) = start {
+        if todo.deadline.is_none() || parse_date_time(start, end_of_day(), warnings) != todo.deadline {
+            ignored.insert("DTSTART".to_string());
+        }
+    }
+    if let Some(rule) = rule {
+        match parse_rrule(&rule, todo.deadline) {
+            Some(recurrence) => todo.recurrence = Some(recurrence),
+            None => warnings.push(format!("\"{}\": can't use RRULE:{}; imported without repeating", todo.title, rule)),
+        }
+    }
+    Some(Entry { todo, uid, parent })
+}
+
+// ltd の繰り返しにできる RRULE だけを読む(毎日、曜日ごと、毎月何日)
+fn parse_rrule(rule: &str, deadline: Option<DateTime<Utc>>) -> Option<Recurrence> {
+    let parts: HashMap<String, String> = rule
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .map(|(key, value)| (key.to_uppercase(), value.to_uppercase()))
+        .collect();
+    if parts.keys().any(|key| !["FREQ", "BYDAY", "BYMONTHDAY", "INTERVAL", "WKST"].contains(&key.as_str())) {
+        return None;
+    }
+    if parts.get("INTERVAL").is_some_and(|interval| interval != "1") {
+        return None;
+    }
+    let due = deadline.map(|deadline| deadline.with_timezone(&Local).date_naive());
+    match parts.get("FREQ")?.as_str() {
+        "DAILY" if !parts.contains_key("BYDAY") && !parts.contains_key("BYMONTHDAY") => Some(Recurrence::Daily),
+        "WEEKLY" if !parts.contains_key("BYMONTHDAY") => {
+            let days = match parts.get("BYDAY") {
+                Some(days) => days
+                    .split(',')
+                    .map(|code| WEEKDAYS.iter().find(|(_, name)| *name == code).map(|(day, _)| *day))
+                    .collect::<Option<Vec<Weekday>>>()?,
+                None => vec![due?.weekday()],
+            };
+            Some(Recurrence::Weekly { days })
+        }
+        "MONTHLY" if !parts.contains_key("BYDAY") => {
+            let day = match parts.get("BYMONTHDAY") {
+                Some(day) => day.parse().ok().filter(|day| (1..=31).contains(day))?,
+                None => due?.day(),
+            };
+            Some(Recurrence::Monthly { day })
+        }
+        _ => None,
+    }
+}
+
+// 日付だけなら date_time の時刻(ローカル時刻)にする
+fn parse_date_time(property: &Property, date_time: NaiveTime, warnings: &mut Vec<String>) -> Option<DateTime<Utc>> {
+    let value = property.value.trim();
+    if property.param("VALUE").is_some_and(|kind| kind.eq_ignore_ascii_case("DATE")) || value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, DATE_FORMAT).ok()?;
+        return to_utc(date.and_time(date_time)).ok();
+    }
+    if let Some(utc) = value.strip_suffix(['Z', 'z']) {
+        return NaiveDateTime::parse_from_str(utc, LOCAL_FORMAT).ok().map(|naive| naive.and_utc());
+    }
+    let naive = NaiveDateTime::parse_from_str(value, LOCAL_FORMAT).ok()?;
+    match property.param("TZID") {
+        Some(tzid) => match time_zone(tzid) {
+            Some(tz) => tz.from_local_datetime(&naive).earliest().map(|date_time| date_time.with_timezone(&Utc)),
+            None => {
+                warnings.push(format!("unknown time zone \"{}\"; read {} as local time", tzid, property.name));
+                to_utc(naive).ok()
+            }
+        },
+        // タイムゾーンの無い日時はどこで見ても同じ時刻(ローカル時刻)
+        None => to_utc(naive).ok(),
+    }
+}
+
+// 「Europe/Berlin」のような IANA の名前。「/mozilla.org/20070129_1/Europe/Berlin」のような前置きも外して探す
+fn time_zone(tzid: &str) -> Option<Tz> {
+    let tzid = tzid.trim_matches('"');
+    let segments: Vec<&str> = tzid.split('/').collect();
+    (0..segments.len()).find_map(|start| segments[start..].join("/").parse::<Tz>().ok())
+}
+
+fn creates_cycle(entries: &[Entry], uids: &HashMap<String, usize>, index: usize, parent: usize) -> bool {
+    let mut current = Some(parent);
+    let mut steps = 0;
+    while let Some(node) = current {
+        if node == index || steps > entries.len() {
+            return true;
+        }
+        current = entries[node].parent.as_ref().and_then(|parent| uids.get(parent).copied());
+        steps += 1;
+    }
+    false
+}
+
+// 空白で始まる行は前の行の続き
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for line in text.lines() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > LINE_LIMIT {
+            folded.push_str("\r\n ");
+            // 続きの行は先頭の空白の分だけ短くなる
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+// 「"」で囲んだ値の中の ; と : は区切りにしない
+fn parse_line(line: &str) -> Option<Property> {
+    let mut quoted = false;
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut value = None;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                parts.push(&line[start..index]);
+                start = index + 1;
+            }
+            ':' if !quoted => {
+                parts.push(&line[start..index]);
+                value = Some(&line[index + 1..]);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let value = value?;
+    let (name, params) = parts.split_first()?;
+    let params = params
+        .iter()
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.trim_matches('"').to_string()))
+        .collect();
+    Some(Property {
+        name: name.to_uppercase(),
+        params,
+        value: value.to_string(),
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+// CATEGORIES のような「,」区切りの値。「\,」は区切りにしない
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    items.last_mut().unwrap().push(if next == 'n' || next == 'N' { '\n' } else { next });
+                }
+            }
+            ',' => items.push(String::new()),
+            c => items.last_mut().unwrap().push(c),
+        }
+    }
+    items
+}
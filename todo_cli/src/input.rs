@@ -1,57 +1,31 @@
+// タスクを指すモードは一覧上の位置ではなくタスクのIDを持つ
 #[derive(PartialEq)]
 pub enum InputMode {
     Normal,
     AddingTitle,
     AddingContent,
+    AddingTags,
     AddingPriority,
     AddingDeadline,
-    ViewingDetails(usize),
-    ViewingSubtaskDetails(usize, usize), // サブタスク詳細表示のモードを追加
+    AddingRecurrence,
+    // どの段のタスクでもよい
+    ViewingDetails(String),
     Searching,
-    EditingTitle(usize),
-    EditingContent(usize),
-    EditingPriority(usize),
-    EditingDeadline(usize),
-    AddingSubtask(usize),
-}
-
-#[derive(PartialEq, Copy, Clone)]
-pub enum PrioritySelection {
-    Low,
-    Medium,
-    High,
-}
-
-impl PrioritySelection {
-    pub fn next(&self) -> Self {
-        match *self {
-            PrioritySelection::Low => PrioritySelection::Medium,
-            PrioritySelection::Medium => PrioritySelection::High,
-            PrioritySelection::High => PrioritySelection::Low,
-        }
-    }
-
-    pub fn prev(&self) -> Self {
-        match *self {
-            PrioritySelection::Low => PrioritySelection::High,
-            PrioritySelection::Medium => PrioritySelection::Low,
-            PrioritySelection::High => PrioritySelection::Medium,
-        }
-    }
-
-    pub fn to_str(&self) -> &'static str {
-        match *self {
-            PrioritySelection::Low => "low",
-            PrioritySelection::Medium => "medium",
-            PrioritySelection::High => "high",
-        }
-    }
-
-    pub fn color(&self) -> tui::style::Color {
-        match *self {
-            PrioritySelection::Low => tui::style::Color::Green,
-            PrioritySelection::Medium => tui::style::Color::Yellow,
-            PrioritySelection::High => tui::style::Color::Red,
-        }
-    }
+    EditingTitle(String),
+    EditingContent(String),
+    EditingTags(String),
+    EditingPriority(String),
+    EditingDeadline(String),
+    EditingRecurrence(String),
+    // 親のタスクのIDと、詳細画面から来たか(戻り先)
+    AddingSubtask(String, bool),
+    LoadFailed,
+    RecoveryView,
+    ResolvingConflict,
+    FilteringTags,
+    SwitchingProject,
+    // 親のタスクのIDを持つ
+    MovingTask(String),
+    // 新しいプロジェクトを作ったら、そこへ移すタスクのID(あれば)
+    NamingProject(Option<String>),
 }
@@ -0,0 +1,87 @@
+use crate::backup::{create_backup, create_migration_backup};
+use crate::project::ProjectSettings;
+use crate::store::Storage;
+use crate::todo::{decode_todos, encode_todos, write_atomic, StoreError, Todo, FORMAT_VERSION};
+use fs2::FileExt;
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+// 1つの整形済みJSONファイルに全タスクを書く。version はファイル内容のハッシュ
+pub struct JsonStorage {
+    path: PathBuf,
+    lock: Option<File>,
+}
+
+fn hash_data(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl JsonStorage {
+    pub fn new(path: PathBuf) -> Self {
+        JsonStorage { path, lock: None }
+    }
+}
+
+impl Storage for JsonStorage {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // rename で置き換える本体ではなく、隣の todos.json.lock に advisory lock をかける
+    fn begin(&mut self) -> Result<(), StoreError> {
+        let mut name = self.path.file_name().map(OsString::from).unwrap_or_default();
+        name.push(".lock");
+        let lock_path = self.path.with_file_name(name);
+        if let Some(dir) = lock_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(lock_path)?;
+        file.lock_exclusive()?;
+        self.lock = Some(file);
+        Ok(())
+    }
+
+    fn finish(&mut self, _commit: bool) -> Result<(), StoreError> {
+        self.lock = None;
+        Ok(())
+    }
+
+    fn version(&mut self) -> Result<Option<u64>, StoreError> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(hash_data(&fs::read(&self.path)?)))
+    }
+
+    fn read(&mut self) -> Result<(Vec<Todo>, ProjectSettings), StoreError> {
+        if !self.path.exists() {
+            return Ok((vec![], ProjectSettings::default()));
+        }
+        let (todos, projects, _) = decode_todos(&fs::read(&self.path)?)?;
+        Ok((todos, projects))
+    }
+
+    fn upgrade(&mut self) -> Result<(), StoreError> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+        let (todos, projects, version) = decode_todos(&fs::read(&self.path)?)?;
+        if version < FORMAT_VERSION {
+            create_migration_backup(&self.path, version)?;
+            self.write(&todos, &projects)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, todos: &[Todo], projects: &ProjectSettings) -> Result<Option<u64>, StoreError> {
+        let data = encode_todos(todos, projects)?;
+        create_backup(&self.path)?;
+        write_atomic(&self.path, data.as_bytes())?;
+        Ok(Some(hash_data(data.as_bytes())))
+    }
+}
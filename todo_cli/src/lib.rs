@@ -0,0 +1,33 @@
+// TUI と CLI の本体。main.rs とテストの両方から使う
+pub mod add_task;
+pub mod app;
+pub mod backup;
+pub mod cli;
+pub mod commands;
+pub mod config;
+pub mod deadline;
+pub mod db_path;
+pub mod delete_task;
+pub mod exchange;
+pub mod handle_input;
+pub mod history;
+pub mod ical;
+pub mod input;
+pub mod json_storage;
+pub mod markdown;
+pub mod merge;
+pub mod migrate;
+pub mod output;
+pub mod priority;
+pub mod project;
+pub mod recurrence;
+pub mod search;
+pub mod sort;
+pub mod sqlite_storage;
+pub mod store;
+pub mod tags;
+pub mod terminal;
+pub mod todo;
+pub mod todotxt;
+pub mod tree;
+pub mod ui;
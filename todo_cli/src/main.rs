@@ -1,84 +1,107 @@
-mod add_task;
-mod delete_task;
-mod handle_input;
-mod input;
-mod search;
-mod sort;
-mod todo;
-mod ui;
-
-use crate::handle_input::handle_input;
-use crate::input::{InputMode, PrioritySelection};
-use crate::sort::SortMode;
-use crate::todo::load_todos;
-use crate::ui::draw_ui;
+use ltd::backup::restore_command;
+use ltd::cli::{Cli, Command, SubCommand};
+use ltd::commands::{add_command, done_command, edit_command, list_command, rm_command, sub_add_command};
+use ltd::config::{config, config_path, load_config, set_config};
+use ltd::db_path::resolve_db_path;
+use ltd::exchange::{export_command, import_command};
+use ltd::app::{App, Effect, Event as AppEvent};
+use ltd::migrate::migrate_command;
+use ltd::terminal::{exit, install_panic_hook, EXIT_ERROR, register_signals, restore_terminal};
+use ltd::store::Store;
+use ltd::ui::draw_ui;
 
+use clap::Parser;
+use crossterm::cursor::{Hide, Show};
 use crossterm::event::{self, Event};
 use crossterm::terminal::{enable_raw_mode, EnterAlternateScreen};
 use crossterm::ExecutableCommand;
 use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tui::backend::CrosstermBackend;
 use tui::Terminal;
-use tui::widgets::ListState;
 
 fn main() -> Result<(), io::Error> {
+    let cli = Cli::parse();
+
+    // 優先度の表示名や色、保存先の形式。書き間違いは TUI を起動する前に知らせる
+    match load_config(&config_path()) {
+        Ok(config) => set_config(config),
+        Err(e) => {
+            eprintln!("ltd: {}", e);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+    let db_path = resolve_db_path(cli.file, cli.local, config().storage);
+
+    if let Some(command) = cli.command {
+        let result = match command {
+            Command::Restore { index } => restore_command(&db_path, index).map_err(|e| e.to_string()),
+            Command::Migrate { to, output } => migrate_command(&db_path, to, output).map_err(|e| e.to_string()),
+            Command::Add { title, fields } => add_command(&db_path, &title, fields),
+            Command::List { project, tags, pending, sort, format } => list_command(&db_path, project, tags, pending, sort, format),
+            Command::Done { id } => done_command(&db_path, &id),
+            Command::Edit { id, title, fields, undone } => edit_command(&db_path, &id, title, fields, undone),
+            Command::Rm { id } => rm_command(&db_path, &id),
+            Command::Import { format, input } => import_command(&db_path, format, input),
+            Command::Export { format, output } => export_command(&db_path, format, output),
+            Command::Sub { command: SubCommand::Add { id, title } } => sub_add_command(&db_path, &id, &title),
+        };
+        if let Err(e) = result {
+            eprintln!("ltd: {}", e);
+            std::process::exit(EXIT_ERROR);
+        }
+        return Ok(());
+    }
+
+    let mut app = App::new(Store::new(db_path));
+    let received_signal = register_signals()?;
+    install_panic_hook();
+
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     stdout.execute(EnterAlternateScreen)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut todos = load_todos();
-    let mut filtered_todos = todos.clone();
-    let mut state = ListState::default();
-    state.select(Some(0));
-
-    let mut input_mode = InputMode::Normal;
-    let mut input_title = String::new();
-    let mut input_content = String::new();
-    let mut input_priority = PrioritySelection::Low;
-    let mut input_deadline = String::new();
-    let mut search_query = String::new();
-    let mut search_state = ListState::default();
-    search_state.select(Some(0));
-    let mut sort_mode = SortMode::ByCompletion;
-    let mut subtask_state = ListState::default();
+    let result = run(&mut terminal, &mut app, &received_signal);
+    restore_terminal();
+    result
+}
 
+fn run(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App, received_signal: &Arc<AtomicUsize>) -> Result<(), io::Error> {
+    let mut last_reload_check = Instant::now();
     loop {
-        terminal.draw(|f| {
-            draw_ui(
-                f,
-                &mut state,
-                &mut search_state,
-                &filtered_todos,
-                &input_mode,
-                &input_title,
-                &input_content,
-                &input_priority,
-                &input_deadline,
-                &search_query,
-                &sort_mode,
-                &mut subtask_state,
-            );
-        })?;
+        terminal.draw(|f| draw_ui(f, app))?;
+
+        // シグナルを拾えるように、キー入力は一定間隔でポーリングする
+        let signal = received_signal.load(Ordering::SeqCst);
+        let event = if signal != 0 {
+            AppEvent::Signal(signal)
+        } else if event::poll(Duration::from_millis(200))? {
+            match event::read()? {
+                Event::Key(key) => AppEvent::Key(key),
+                _ => continue,
+            }
+        } else if last_reload_check.elapsed() >= Duration::from_secs(1) {
+            // 待機中に他の ltd が保存した内容を取り込む
+            last_reload_check = Instant::now();
+            AppEvent::Tick
+        } else {
+            continue;
+        };
 
-        if let Event::Key(key) = event::read()? {
-            handle_input(
-                key,
-                &mut todos,
-                &mut filtered_todos,
-                &mut state,
-                &mut input_mode,
-                &mut input_title,
-                &mut input_content,
-                &mut input_priority,
-                &mut input_deadline,
-                &mut search_query,
-                &mut search_state,
-                &mut sort_mode,
-                &mut terminal,
-                &mut subtask_state,
-            )?;
+        for effect in app.update(event) {
+            match effect {
+                Effect::HideCursor => {
+                    terminal.backend_mut().execute(Hide)?;
+                }
+                Effect::ShowCursor => {
+                    terminal.backend_mut().execute(Show)?;
+                }
+                Effect::Quit(code) => exit(code),
+            }
         }
     }
 }
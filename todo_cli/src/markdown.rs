@@ -0,0 +1,213 @@
+use crate::deadline::{format_compact_deadline, parse_absolute_deadline};
+use crate::priority::Priority;
+use crate::project::project_of;
+use crate::tags::{extract_tags, format_tags};
+use crate::todo::Todo;
+
+// GitHub 形式のチェックリストとの変換
+// - [ ] タイトル #タグ !優先度 due:期限
+//   > メモ(字下げした引用。見出しや項目に見える行もメモのまま読み戻せる)
+//   - [x] サブタスク
+// 見出し(## プロジェクト)の下のタスクはそのプロジェクトに入る
+
+const INDENT: &str = "  ";
+
+// 戻り値は書き出した文字列と、Markdown に書けなかったものの一覧
+pub fn export(todos: &[Todo]) -> (String, Vec<String>) {
+    fn push(todo: &Todo, depth: usize, lines: &mut Vec<String>, warnings: &mut Vec<String>) {
+        let indent = INDENT.repeat(depth);
+        let mut words = vec![format!("{}- [{}] {}", indent, if todo.done { "x" } else { " " }, todo.title)];
+        if !todo.tags.is_empty() {
+            words.push(format_tags(&todo.tags));
+        }
+        // 何も書かなければ low(TUI で追加したときと同じ)
+        if todo.priority != Priority::default() {
+            words.push(format!("!{}", todo.priority.to_str()));
+        }
+        if let Some(deadline) = todo.deadline {
+            words.push(format!("due:{}", format_compact_deadline(deadline)));
+        }
+        lines.push(words.join(" "));
+        for line in todo.content.lines().map(str::trim_end) {
+            let quoted = if line.is_empty() { ">".to_string() } else { format!("> {}", line) };
+            lines.push(format!("{}{}{}", indent, INDENT, quoted));
+        }
+        if let Some(recurrence) = &todo.recurrence {
+            warnings.push(format!("\"{}\": repeat rule \"{}\" is not exported", todo.title, recurrence));
+        }
+        for subtask in &todo.subtasks {
+            push(subtask, depth + 1, lines, warnings);
+        }
+    }
+
+    let mut lines = vec![];
+    let mut warnings = vec![];
+    // プロジェクトが変わるところに見出しを入れる(並び順はそのまま)
+    let mut project = None;
+    for todo in todos {
+        if project != Some(project_of(todo)) {
+            if project.is_some() {
+                lines.push(String::new());
+            }
+            project = Some(project_of(todo));
+            lines.push(format!("## {}", project_of(todo)));
+            lines.push(String::new());
+        }
+        push(todo, 0, &mut lines, &mut warnings);
+    }
+    let mut text = lines.join("\n");
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    (text, warnings)
+}
+
+// チェックリストの行を読む。行頭の字下げでサブタスクの深さを決める
+// 戻り値は読み込んだタスクと、読めなかった注記や読み飛ばした行の一覧
+pub fn import(text: &str) -> (Vec<Todo>, Vec<String>) {
+    let mut todos: Vec<Todo> = vec![];
+    let mut warnings = vec![];
+    let mut project = String::new();
+    // 今の項目とその親たち(字下げの幅, 何番目の子か)
+    let mut stack: Vec<(usize, usize)> = vec![];
+    let mut skipped = 0;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let indent = indent_width(line);
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        // 項目より深く字下げした行はその項目のメモかサブタスク。引用(>)と見出しに見える行はメモにする
+        let under_item = stack.last().is_some_and(|&(item_indent, _)| indent > item_indent);
+        let note = match trimmed.strip_prefix('>') {
+            Some(quoted) if under_item => Some(quoted.strip_prefix(' ').unwrap_or(quoted)),
+            _ if under_item && heading(trimmed).is_some() => Some(trimmed),
+            _ => None,
+        };
+        if note.is_none() {
+            if let Some(heading) = heading(trimmed) {
+                project = heading.to_string();
+                stack.clear();
+                continue;
+            }
+        }
+        let item = note.is_none().then(|| checklist_item(trimmed)).flatten();
+        let Some((done, rest)) = item else {
+            if !under_item {
+                skipped += 1;
+                continue;
+            }
+            if let Some(todo) = current(&mut todos, &stack) {
+                if !todo.content.is_empty() {
+                    todo.content.push('\n');
+                }
+                todo.content.push_str(note.unwrap_or(trimmed));
+            }
+            continue;
+        };
+
+        let mut todo = parse_item(rest, number, &mut warnings);
+        if todo.title.is_empty() {
+            warnings.push(format!("line {}: no title; skipped", number));
+            continue;
+        }
+        todo.done = done;
+        while stack.last().is_some_and(|&(item_indent, _)| item_indent >= indent) {
+            stack.pop();
+        }
+        match current(&mut todos, &stack) {
+            Some(parent) => {
+                parent.subtasks.push(todo);
+                parent.expanded = true;
+                let position = parent.subtasks.len() - 1;
+                stack.push((indent, position));
+            }
+            None => {
+                todo.project = project.clone();
+                todos.push(todo);
+                stack.push((indent, todos.len() - 1));
+            }
+        }
+    }
+    if skipped > 0 {
+        warnings.push(match skipped {
+            1 => "skipped 1 line that is not a checklist item".to_string(),
+            _ => format!("skipped {} lines that are not checklist items", skipped),
+        });
+    }
+    (todos, warnings)
+}
+
+// タイトルと注記(#タグ !優先度 due:期限)。読めない注記はタイトルに残して知らせる
+fn parse_item(rest: &str, number: usize, warnings: &mut Vec<String>) -> Todo {
+    let mut todo = Todo::new(String::new(), String::new(), Priority::default(), None);
+    let mut title = vec![];
+    for word in rest.split_whitespace() {
+        if let Some(level) = word.strip_prefix('!').filter(|level| !level.is_empty()) {
+            if let Some(priority) = Priority::parse(level) {
+                todo.priority = priority;
+                continue;
+            }
+            warnings.push(format!("line {}: unknown priority {}; kept it in the title", number, word));
+        } else if let Some(value) = word.strip_prefix("due:") {
+            match parse_absolute_deadline(value) {
+                Some(deadline) => {
+                    todo.deadline = Some(deadline);
+                    continue;
+                }
+                None => warnings.push(format!("line {}: can't read {}; kept it in the title", number, word)),
+            }
+        }
+        title.push(word);
+    }
+    let (title, tags) = extract_tags(&title.join(" "));
+    todo.title = title;
+    todo.tags = tags;
+    todo
+}
+
+// stack の一番上の項目
+fn current<'a>(todos: &'a mut [Todo], stack: &[(usize, usize)]) -> Option<&'a mut Todo> {
+    let (&(_, first), rest) = stack.split_first()?;
+    let mut todo = todos.get_mut(first)?;
+    for &(_, index) in rest {
+        todo = todo.subtasks.get_mut(index)?;
+    }
+    Some(todo)
+}
+
+// タブは4文字分として数える
+fn indent_width(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+fn heading(line: &str) -> Option<&str> {
+    let rest = line.trim_start_matches('#');
+    let level = line.len() - rest.len();
+    (1..=6).contains(&level).then_some(rest).filter(|rest| rest.starts_with(' ')).map(str::trim).filter(|name| !name.is_empty())
+}
+
+// 「- [ ] 」「* [x] 」「1. [ ] 」のような行。戻り値は完了かどうかと残りの文字列
+fn checklist_item(line: &str) -> Option<(bool, &str)> {
+    let rest = match line.strip_prefix(['-', '*', '+']) {
+        Some(rest) => rest,
+        None => {
+            let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            line[digits..].strip_prefix(['.', ')']).filter(|_| digits > 0)?
+        }
+    };
+    let rest = rest.strip_prefix(' ')?.trim_start();
+    let (done, rest) = if let Some(rest) = rest.strip_prefix("[ ]") {
+        (false, rest)
+    } else if let Some(rest) = rest.strip_prefix("[x]").or_else(|| rest.strip_prefix("[X]")) {
+        (true, rest)
+    } else {
+        return None;
+    };
+    let rest = rest.trim();
+    (!rest.is_empty()).then_some((done, rest))
+}
@@ -0,0 +1,70 @@
+use crate::todo::Todo;
+use std::collections::HashMap;
+
+// 同じタスクを自分と他のプロセスの両方が違う内容に変えたもの
+pub struct Conflict {
+    pub index: usize,
+    pub mine: Option<Todo>,
+    pub theirs: Option<Todo>,
+}
+
+pub struct Merge {
+    pub todos: Vec<Todo>,
+    pub conflicts: Vec<Conflict>,
+}
+
+pub type Key = String;
+
+// タスクはIDで対応付ける
+pub fn keyed(todos: &[Todo]) -> Vec<(Key, &Todo)> {
+    todos.iter().map(|todo| (todo.id.clone(), todo)).collect()
+}
+
+// base: 最後に読み書きした内容 / mine: メモリ上の内容 / theirs: 今ディスクにある内容
+// 片方だけが変えたものはそちらを採用し、両方が違う内容に変えたものだけを Conflict にする。
+// 衝突したタスクは theirs(無ければ mine)の内容で仮に置いておく
+pub fn merge(base: &[Todo], mine: &[Todo], theirs: &[Todo]) -> Merge {
+    let base_map: HashMap<Key, &Todo> = keyed(base).into_iter().collect();
+    let mine_map: HashMap<Key, &Todo> = keyed(mine).into_iter().collect();
+    let theirs_map: HashMap<Key, &Todo> = keyed(theirs).into_iter().collect();
+    let mut todos = vec![];
+    let mut conflicts = vec![];
+
+    for (key, t) in keyed(theirs) {
+        match (base_map.get(&key), mine_map.get(&key)) {
+            (_, Some(m)) if *m == t => todos.push(t.clone()),
+            (Some(b), Some(m)) if *b == t => todos.push((*m).clone()),
+            (Some(b), Some(m)) if b == m => todos.push(t.clone()),
+            (Some(b), None) if *b == t => {}
+            (None, None) => todos.push(t.clone()),
+            (_, m) => {
+                conflicts.push(Conflict {
+                    index: todos.len(),
+                    mine: m.map(|m| (*m).clone()),
+                    theirs: Some(t.clone()),
+                });
+                todos.push(t.clone());
+            }
+        }
+    }
+
+    for (key, m) in keyed(mine) {
+        if theirs_map.contains_key(&key) {
+            continue;
+        }
+        match base_map.get(&key) {
+            None => todos.push(m.clone()),
+            Some(b) if *b == m => {}
+            Some(_) => {
+                conflicts.push(Conflict {
+                    index: todos.len(),
+                    mine: Some(m.clone()),
+                    theirs: None,
+                });
+                todos.push(m.clone());
+            }
+        }
+    }
+
+    Merge { todos, conflicts }
+}
@@ -0,0 +1,55 @@
+use crate::cli::StorageFormat;
+use crate::config::config_path;
+use crate::store::{is_sqlite_path, Store};
+use crate::todo::StoreError;
+use std::io;
+use std::path::{Path, PathBuf};
+
+fn already_exists(path: &Path) -> StoreError {
+    StoreError::Io(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists", path.display()),
+    ))
+}
+
+// 今の保存先の中身を別の形式の保存先へコピーする。元のファイルはそのまま残す
+pub fn migrate_command(source: &Path, to: StorageFormat, output: Option<PathBuf>) -> Result<(), StoreError> {
+    let target = output.unwrap_or_else(|| match to {
+        StorageFormat::Sqlite => source.with_extension("db"),
+        StorageFormat::Json => source.with_extension("json"),
+    });
+    if (to == StorageFormat::Sqlite) != is_sqlite_path(&target) {
+        return Err(StoreError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} does not have an extension for that format", target.display()),
+        )));
+    }
+    if target.exists() {
+        return Err(already_exists(&target));
+    }
+
+    let mut origin = Store::new(source.to_path_buf());
+    let mut todos = origin.load()?;
+    let mut destination = Store::new(target.clone());
+    destination.load()?;
+    destination.set_projects(origin.projects().clone());
+    destination.save(&mut todos)?;
+    println!(
+        "Copied {} tasks from {} to {}",
+        todos.len(),
+        source.display(),
+        target.display()
+    );
+    // 保存先の形式は自動では切り替えないので、使い方を知らせる
+    let format = match to {
+        StorageFormat::Sqlite => "sqlite",
+        StorageFormat::Json => "json",
+    };
+    println!(
+        "To use it, set format = \"{}\" under [storage] in {} (or pass --file {})",
+        format,
+        config_path().display(),
+        target.display()
+    );
+    Ok(())
+}
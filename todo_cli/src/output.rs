@@ -0,0 +1,190 @@
+use crate::cli::ListFormat;
+use crate::commands::short_id;
+use crate::deadline::{describe_remaining, format_deadline, Urgency};
+use crate::priority::Priority;
+use crate::project::project_of;
+use crate::tags::format_tags;
+use crate::todo::Todo;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// 列の間の空白
+const GAP: &str = "  ";
+// 端末が狭くても、タイトルとタグはこれより細くしない
+const MIN_TITLE_WIDTH: usize = 12;
+const MIN_TAGS_WIDTH: usize = 5;
+
+// 指定が無ければ端末には表、パイプやファイルには1行ずつのテキストを出す。表も端末でなければテキストにする
+pub fn resolve_format(format: Option<ListFormat>, is_tty: bool) -> ListFormat {
+    match format {
+        Some(ListFormat::Table) | None if !is_tty => ListFormat::Plain,
+        Some(format) => format,
+        None => ListFormat::Table,
+    }
+}
+
+// 保存形式と同じ項目に、計算で出す項目を足したもの。サブタスクも同じ形で入れ子にする
+// 足す項目: short_id / completion_rate(0〜100) / overdue / urgency(overdue, due_soon, upcoming, later か null)
+// pending なら完了したサブタスクは出さない(completion_rate は出さなかったものも含めて計算する)
+pub fn json_value(todo: &Todo, pending: bool, now: DateTime<Utc>) -> Value {
+    let mut value = serde_json::to_value(todo).unwrap_or(Value::Null);
+    if let Value::Object(fields) = &mut value {
+        let urgency = todo.deadline.filter(|_| !todo.done).map(|deadline| match Urgency::of(deadline, now) {
+            Urgency::Overdue => "overdue",
+            Urgency::DueSoon => "due_soon",
+            Urgency::Upcoming => "upcoming",
+            Urgency::Later => "later",
+        });
+        fields.insert("short_id".to_string(), Value::from(short_id(&todo.id)));
+        fields.insert("completion_rate".to_string(), Value::from((todo.completion_rate() as f64 * 10.0).round() / 10.0));
+        fields.insert("overdue".to_string(), Value::from(urgency == Some("overdue")));
+        fields.insert("urgency".to_string(), urgency.map_or(Value::Null, Value::from));
+        let subtasks = shown_subtasks(todo, pending).map(|subtask| json_value(subtask, pending, now)).collect();
+        fields.insert("subtasks".to_string(), Value::Array(subtasks));
+    }
+    value
+}
+
+pub fn json(todos: &[Todo], pending: bool, now: DateTime<Utc>) -> String {
+    let values: Vec<Value> = todos.iter().map(|todo| json_value(todo, pending, now)).collect();
+    serde_json::to_string_pretty(&values).unwrap_or_default()
+}
+
+// 親のタスク1つにつき1行(サブタスクはその中に入れ子)
+pub fn ndjson(todos: &[Todo], pending: bool, now: DateTime<Utc>) -> Vec<String> {
+    todos.iter().map(|todo| json_value(todo, pending, now).to_string()).collect()
+}
+
+// 一覧に出すサブタスク。pending なら完了したものを外す
+fn shown_subtasks(todo: &Todo, pending: bool) -> impl Iterator<Item = &Todo> {
+    todo.subtasks.iter().filter(move |subtask| !(pending && subtask.done))
+}
+
+// 1行に1つのタスク。サブタスクは親の下に字下げして並べる
+pub fn plain_lines(todos: &[Todo], show_project: bool, pending: bool, now: DateTime<Utc>) -> Vec<String> {
+    fn push(todo: &Todo, depth: usize, show_project: bool, pending: bool, now: DateTime<Utc>, lines: &mut Vec<String>) {
+        let mut line = format!("{}  {}[{}] ", short_id(&todo.id), "  ".repeat(depth), if todo.done { "x" } else { " " });
+        if show_project && depth == 0 {
+            line.push_str(&format!("[{}] ", project_of(todo)));
+        }
+        line.push_str(&todo.title);
+        let mut marks = vec![];
+        if !todo.subtasks.is_empty() {
+            marks.push(format!("{:.0}%", todo.completion_rate()));
+        }
+        if todo.priority != Priority::default() {
+            marks.push(format!("!{}", todo.priority.name()));
+        }
+        if let Some(deadline) = todo.deadline {
+            marks.push(format!("due {} ({})", format_deadline(deadline), describe_remaining(deadline, now)));
+        }
+        if let Some(recurrence) = &todo.recurrence {
+            marks.push(format!("repeats {}", recurrence));
+        }
+        if !todo.tags.is_empty() {
+            marks.push(format_tags(&todo.tags));
+        }
+        for mark in marks {
+            line.push_str(GAP);
+            line.push_str(&mark);
+        }
+        lines.push(line);
+        for subtask in shown_subtasks(todo, pending) {
+            push(subtask, depth + 1, show_project, pending, now, lines);
+        }
+    }
+
+    let mut lines = vec![];
+    for todo in todos {
+        push(todo, 0, show_project, pending, now, &mut lines);
+    }
+    lines
+}
+
+// 見出し付きの表。幅が足りなければタイトル、次にタグの列を縮めて末尾を … にする
+pub fn table_lines(todos: &[Todo], show_project: bool, pending: bool, width: usize, now: DateTime<Utc>) -> Vec<String> {
+    fn push(todo: &Todo, depth: usize, show_project: bool, pending: bool, now: DateTime<Utc>, rows: &mut Vec<Vec<String>>) {
+        let mut row = vec![
+            short_id(&todo.id),
+            format!("{}[{}] {}", "  ".repeat(depth), if todo.done { "x" } else { " " }, todo.title),
+        ];
+        if show_project {
+            row.push(if depth == 0 { project_of(todo).to_string() } else { String::new() });
+        }
+        row.push(if todo.priority == Priority::default() { String::new() } else { todo.priority.name().to_string() });
+        row.push(todo.deadline.map(format_deadline).unwrap_or_default());
+        row.push(todo.deadline.map(|deadline| describe_remaining(deadline, now)).unwrap_or_default());
+        row.push(if todo.subtasks.is_empty() { String::new() } else { format!("{:.0}%", todo.completion_rate()) });
+        row.push(format_tags(&todo.tags));
+        rows.push(row);
+        for subtask in shown_subtasks(todo, pending) {
+            push(subtask, depth + 1, show_project, pending, now, rows);
+        }
+    }
+
+    let mut header = vec!["ID", "TITLE"];
+    if show_project {
+        header.push("PROJECT");
+    }
+    header.extend(["PRIORITY", "DUE", "LEFT", "DONE", "TAGS"]);
+    let mut rows = vec![header.into_iter().map(String::from).collect::<Vec<_>>()];
+    for todo in todos {
+        push(todo, 0, show_project, pending, now, &mut rows);
+    }
+
+    // どの行も空の列(期限のあるタスクが無いときの DUE など)は出さない
+    let used: Vec<bool> = (0..rows[0].len()).map(|column| column < 2 || rows[1..].iter().any(|row| !row[column].is_empty())).collect();
+    let tags_used = used[used.len() - 1];
+    for row in &mut rows {
+        let mut column = 0;
+        row.retain(|_| {
+            column += 1;
+            used[column - 1]
+        });
+    }
+
+    let columns = rows[0].len();
+    let mut shrinkable = vec![(1, MIN_TITLE_WIDTH)];
+    if tags_used {
+        shrinkable.push((columns - 1, MIN_TAGS_WIDTH));
+    }
+    let mut widths: Vec<usize> = (0..columns).map(|column| rows.iter().map(|row| row[column].width()).max().unwrap_or(0)).collect();
+    let total = widths.iter().sum::<usize>() + GAP.len() * (columns - 1);
+    let mut excess = total.saturating_sub(width);
+    for (column, min) in shrinkable {
+        let shrink = excess.min(widths[column].saturating_sub(min));
+        widths[column] -= shrink;
+        excess -= shrink;
+    }
+
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, &width)| pad(&truncate(cell, width), width)).collect();
+            cells.join(GAP).trim_end().to_string()
+        })
+        .collect()
+}
+
+// 表示幅で width に収める。はみ出すときは最後を … にする
+fn truncate(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        truncated.push(c);
+        used += w;
+    }
+    truncated.push('…');
+    truncated
+}
+
+fn pad(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(text.width())))
+}
@@ -0,0 +1,65 @@
+use crate::config::config;
+use serde::{Deserialize, Serialize};
+use tui::style::Color;
+
+// 低い順に並べてある。j/k ではこの順に巡回する
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    None,
+    #[default]
+    Low,
+    Medium,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 5] = [
+        Priority::None,
+        Priority::Low,
+        Priority::Medium,
+        Priority::High,
+        Priority::Urgent,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Priority::ALL.iter().position(|p| *p == self).unwrap_or(0);
+        Priority::ALL[(index + 1) % Priority::ALL.len()]
+    }
+
+    pub fn prev(self) -> Self {
+        let index = Priority::ALL.iter().position(|p| *p == self).unwrap_or(0);
+        Priority::ALL[(index + Priority::ALL.len() - 1) % Priority::ALL.len()]
+    }
+
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Priority::None => "none",
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        }
+    }
+
+    // 大文字小文字は区別しない
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().to_lowercase();
+        Priority::ALL.into_iter().find(|p| p.to_str() == s)
+    }
+
+    // 優先度順に並べるときのキー(urgent が先頭、none が最後)
+    pub fn rank(self) -> usize {
+        Priority::ALL.len() - 1 - Priority::ALL.iter().position(|p| *p == self).unwrap_or(0)
+    }
+
+    // 表示名と色は設定ファイルで変えられる
+    pub fn name(self) -> &'static str {
+        config().priority_name(self)
+    }
+
+    pub fn color(self) -> Color {
+        config().priority_color(self)
+    }
+}
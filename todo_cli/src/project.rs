@@ -0,0 +1,150 @@
+use crate::search::matches_query;
+use crate::sort::{sort_todos, SortMode};
+use crate::tags::TagFilter;
+use crate::todo::Todo;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tui::widgets::ListState;
+
+// project が空のタスク(プロジェクトを作る前からあるもの)もここに入る
+pub const DEFAULT_PROJECT: &str = "Inbox";
+
+pub fn project_of(todo: &Todo) -> &str {
+    if todo.project.is_empty() {
+        DEFAULT_PROJECT
+    } else {
+        &todo.project
+    }
+}
+
+// プロジェクトの一覧とタスクの数。Inbox が先頭で、あとは一覧に出てくる順
+pub fn projects(todos: &[Todo], created: &[String]) -> Vec<(String, usize)> {
+    let mut projects: Vec<(String, usize)> = vec![(DEFAULT_PROJECT.to_string(), 0)];
+    for name in todos.iter().map(project_of).chain(created.iter().map(String::as_str)) {
+        if !projects.iter().any(|(project, _)| project == name) {
+            projects.push((name.to_string(), 0));
+        }
+    }
+    for todo in todos {
+        if let Some((_, count)) = projects.iter_mut().find(|(project, _)| project == project_of(todo)) {
+            *count += 1;
+        }
+    }
+    projects
+}
+
+// プロジェクトを選ぶ一覧の行
+pub enum PickerEntry {
+    AllProjects,
+    Project(String, usize),
+    NewProject,
+}
+
+pub fn picker_entries(todos: &[Todo], created: &[String], include_all: bool) -> Vec<PickerEntry> {
+    let mut entries = vec![];
+    if include_all {
+        entries.push(PickerEntry::AllProjects);
+    }
+    entries.extend(projects(todos, created).into_iter().map(|(name, count)| PickerEntry::Project(name, count)));
+    entries.push(PickerEntry::NewProject);
+    entries
+}
+
+// タスクと一緒に保存するプロジェクトの設定
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ProjectSettings {
+    // タスクの無いプロジェクト
+    #[serde(default)]
+    pub created: Vec<String>,
+    // ByCompletion 以外にしたプロジェクトの並び順
+    #[serde(default)]
+    pub sort_modes: Vec<ProjectSortMode>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProjectSortMode {
+    // None なら All projects
+    pub project: Option<String>,
+    pub mode: SortMode,
+}
+
+// 一覧に何を出すか。プロジェクトとタグで絞り込む
+#[derive(Default)]
+pub struct ListView {
+    // None なら全プロジェクトをまとめて出す
+    pub project: Option<String>,
+    pub tags: TagFilter,
+    // 検索窓で Enter したときの文字列。空なら絞り込まない
+    pub search: String,
+    // プロジェクトごとの並び順(今のプロジェクトのものは sort_mode の方にある)
+    sort_modes: BTreeMap<Option<String>, SortMode>,
+    // 作ったり切り替えたりしたプロジェクト。タスクがまだ無くても一覧に出す
+    pub created: Vec<String>,
+    // プロジェクトを選ぶ一覧のカーソルと、新しいプロジェクトの名前の入力
+    pub picker: ListState,
+    pub new_project: String,
+}
+
+impl ListView {
+    pub fn in_project(&self, todo: &Todo) -> bool {
+        self.project.as_deref().is_none_or(|project| project_of(todo) == project)
+    }
+
+    pub fn matches(&self, todo: &Todo) -> bool {
+        self.in_project(todo) && self.tags.matches(todo) && (self.search.is_empty() || matches_query(todo, &self.search))
+    }
+
+    // 新しいタスクを入れるプロジェクト
+    pub fn target_project(&self) -> String {
+        self.project.clone().unwrap_or_else(|| DEFAULT_PROJECT.to_string())
+    }
+
+    // 今のプロジェクトの並び順を覚えておき、切り替え先のものに差し替える
+    pub fn switch(&mut self, project: Option<String>, sort_mode: &mut SortMode) {
+        if let Some(name) = &project {
+            if !self.created.contains(name) {
+                self.created.push(name.clone());
+            }
+        }
+        let previous = std::mem::replace(&mut self.project, project);
+        self.sort_modes.insert(previous, sort_mode.clone());
+        *sort_mode = self.sort_modes.get(&self.project).cloned().unwrap_or(SortMode::ByCompletion);
+        self.tags.selected.clear();
+        self.search.clear();
+    }
+
+    // 保存する設定。sort_mode は今のプロジェクトの並び順
+    pub fn settings(&self, todos: &[Todo], sort_mode: &SortMode) -> ProjectSettings {
+        let mut sort_modes = self.sort_modes.clone();
+        sort_modes.insert(self.project.clone(), sort_mode.clone());
+        ProjectSettings {
+            created: self.created.iter().filter(|name| !todos.iter().any(|todo| project_of(todo) == *name)).cloned().collect(),
+            sort_modes: sort_modes
+                .into_iter()
+                .filter(|(_, mode)| *mode != SortMode::ByCompletion)
+                .map(|(project, mode)| ProjectSortMode { project, mode })
+                .collect(),
+        }
+    }
+
+    // 読み込んだ設定を取り込み(プロジェクトは足すだけ)、今のプロジェクトの並び順を sort_mode に入れる
+    pub fn apply(&mut self, settings: &ProjectSettings, sort_mode: &mut SortMode) {
+        for name in &settings.created {
+            if !self.created.contains(name) {
+                self.created.push(name.clone());
+            }
+        }
+        self.sort_modes = settings.sort_modes.iter().map(|saved| (saved.project.clone(), saved.mode.clone())).collect();
+        *sort_mode = self.sort_modes.get(&self.project).cloned().unwrap_or(SortMode::ByCompletion);
+    }
+
+    // 今のプロジェクトのタスクだけを並べ替える(他のプロジェクトのタスクの位置は変えない)
+    pub fn sort(&self, todos: &mut [Todo], mode: SortMode) {
+        let positions: Vec<usize> = (0..todos.len()).filter(|&i| self.in_project(&todos[i])).collect();
+        let mut sorted: Vec<Todo> = positions.iter().map(|&i| todos[i].clone()).collect();
+        sort_todos(&mut sorted, mode);
+        for (position, todo) in positions.into_iter().zip(sorted) {
+            todos[position] = todo;
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::deadline::{end_of_day, parse_deadline, parse_weekday, to_utc};
+use chrono::prelude::*;
+use chrono::{Days, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// 繰り返しの規則。完了にすると記録を残して期限が次の回へ進む
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "every", rename_all = "snake_case")]
+pub enum Recurrence {
+    Daily,
+    Weekly { days: Vec<Weekday> },
+    // 月の日数より大きい日はその月の末日
+    Monthly { day: u32 },
+    // 期限からではなく、完了した日から数える
+    AfterCompletion { days: u32 },
+}
+
+// 完了した回の記録
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub deadline: Option<DateTime<Utc>>,
+    pub done_at: DateTime<Utc>,
+}
+
+impl Recurrence {
+    // 入力を解釈する。空か none なら繰り返しなし
+    //
+    //   daily / every day
+    //   weekly(today の曜日) / weekly mon,thu
+    //   monthly(today の日) / monthly 15
+    //   every 3 days after completion / every 3d after done / after 3d
+    pub fn parse(input: &str, today: NaiveDate) -> Result<Option<Self>, String> {
+        let input = input.trim().to_lowercase();
+        let error = || format!("can't understand repeat \"{}\"", input);
+        let words: Vec<&str> = input.split([' ', ',']).filter(|word| !word.is_empty()).collect();
+        let rule = match words.as_slice() {
+            [] | ["none"] => return Ok(None),
+            ["daily"] | ["every", "day"] => Recurrence::Daily,
+            ["weekly"] | ["every", "week"] => Recurrence::Weekly {
+                days: vec![today.weekday()],
+            },
+            ["weekly", days @ ..] | ["every", days @ ..] if !days.is_empty() && days.iter().all(|day| parse_weekday(day).is_some()) => {
+                let mut days: Vec<Weekday> = days.iter().filter_map(|day| parse_weekday(day)).collect();
+                days.sort_by_key(|day| day.num_days_from_monday());
+                days.dedup();
+                Recurrence::Weekly { days }
+            }
+            ["monthly"] | ["every", "month"] => Recurrence::Monthly { day: today.day() },
+            ["monthly", day] => match day.trim_end_matches(|c: char| c.is_alphabetic()).parse() {
+                Ok(day) if (1..=31).contains(&day) => Recurrence::Monthly { day },
+                _ => return Err(error()),
+            },
+            ["after", amount] | ["every", amount, "after", "completion" | "done"] => Recurrence::AfterCompletion {
+                days: parse_days(amount).ok_or_else(error)?,
+            },
+            ["after", amount, "days" | "day"] | ["every", amount, "days" | "day", "after", "completion" | "done"] => {
+                Recurrence::AfterCompletion {
+                    days: amount.parse().ok().filter(|days| *days > 0).ok_or_else(error)?,
+                }
+            }
+            _ => return Err(error()),
+        };
+        Ok(Some(rule))
+    }
+
+    // 期限の無いタスクに繰り返しを付けたときの最初の期限(今日以降で最初の回の日の終わり)
+    pub fn first_deadline(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let today = now.with_timezone(&Local).date_naive();
+        let date = match self {
+            Recurrence::AfterCompletion { days } => today.checked_add_days(Days::new(*days as u64))?,
+            _ => self.next_date(today.pred_opt()?)?,
+        };
+        to_utc(date.and_time(end_of_day())).ok()
+    }
+
+    // 完了したときの次の期限。期限の時刻はそのまま、溜まっていた回は飛ばして now より後にする
+    pub fn next_deadline(&self, deadline: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        // 期限が無かったら今日の終わりを前回の期限とみなす
+        let local = match deadline {
+            Some(deadline) => deadline.with_timezone(&Local).naive_local(),
+            None => now.with_timezone(&Local).date_naive().and_time(end_of_day()),
+        };
+        let time = local.time();
+        let mut date = match self {
+            Recurrence::AfterCompletion { days } => {
+                let done_on = now.with_timezone(&Local).date_naive();
+                return to_utc(done_on.checked_add_days(Days::new(*days as u64))?.and_time(time)).ok();
+            }
+            _ => self.next_date(local.date())?,
+        };
+        loop {
+            let next = to_utc(date.and_time(time)).ok()?;
+            if next > now {
+                return Some(next);
+            }
+            date = self.next_date(date)?;
+        }
+    }
+
+    // after より後で最初の回の日
+    fn next_date(&self, after: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Recurrence::Daily => after.succ_opt(),
+            Recurrence::Weekly { days } => (1..=7)
+                .filter_map(|offset| after.checked_add_days(Days::new(offset)))
+                .find(|date| days.is_empty() || days.contains(&date.weekday())),
+            Recurrence::Monthly { day } => {
+                let this_month = day_in_month(after.year(), after.month(), *day)?;
+                if this_month > after {
+                    return Some(this_month);
+                }
+                let next_month = NaiveDate::from_ymd_opt(after.year(), after.month(), 1)?.checked_add_months(Months::new(1))?;
+                day_in_month(next_month.year(), next_month.month(), *day)
+            }
+            Recurrence::AfterCompletion { days } => after.checked_add_days(Days::new(*days as u64)),
+        }
+    }
+}
+
+// parse で読み戻せる形で書く
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly { days } => {
+                let days: Vec<String> = days.iter().map(|day| day.to_string().to_lowercase()).collect();
+                write!(f, "weekly {}", days.join(","))
+            }
+            Recurrence::Monthly { day } => write!(f, "monthly {}", day),
+            Recurrence::AfterCompletion { days } => write!(f, "every {}d after completion", days),
+        }
+    }
+}
+
+fn day_in_month(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+    (1..=day).rev().find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
+// 3d / 2w
+fn parse_days(amount: &str) -> Option<u32> {
+    let (number, unit) = amount.split_at(amount.find(|c: char| !c.is_ascii_digit())?);
+    let number: u32 = number.parse().ok().filter(|number| *number > 0)?;
+    match unit {
+        "d" => Some(number),
+        "w" => number.checked_mul(7),
+        _ => None,
+    }
+}
+
+// 期限と繰り返しの入力を解釈する。期限の無い繰り返しのタスクには最初の回の期限を付ける
+pub fn parse_schedule(input_deadline: &str, input_recurrence: &str) -> Result<(Option<DateTime<Utc>>, Option<Recurrence>), String> {
+    let now = Local::now();
+    let deadline = parse_deadline(input_deadline, now)?;
+    let recurrence = Recurrence::parse(input_recurrence, now.date_naive())?;
+    let deadline = match (&recurrence, deadline) {
+        (Some(recurrence), None) => recurrence.first_deadline(now.with_timezone(&Utc)),
+        (_, deadline) => deadline,
+    };
+    Ok((deadline, recurrence))
+}
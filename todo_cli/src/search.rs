@@ -1,14 +1,195 @@
+use crate::project::ListView;
 use crate::todo::Todo;
-use strsim::levenshtein;
 
-pub fn search_todos(todos: &Vec<Todo>, query: &str) -> Vec<Todo> {
-    let threshold = 3; // 許容される編集距離の閾値
+// あいまい一致の点数(fzf と同じ考え方)
+// 文字が合うごとに加点し、単語の頭や続けて合った文字はさらに加点、飛ばした文字の分は減点する
+const SCORE_MATCH: i64 = 16;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CONSECUTIVE: i64 = 8;
+const PENALTY_GAP_OPEN: i64 = 3;
+const PENALTY_GAP: i64 = 1;
 
-    todos
+// タイトル以外で合ったときは、タイトルで合うより少し下に並べる
+const PENALTY_TAG: i64 = 4;
+const PENALTY_SUBTASK: i64 = 6;
+const PENALTY_CONTENT: i64 = 8;
+
+const NO_MATCH: i64 = i64::MIN / 2;
+
+// query の文字が text に順に(飛び飛びでよい)出てくれば一致。点数と、合った文字の位置(文字単位)を返す
+// query に大文字が入っていなければ大文字小文字を区別しない
+pub fn fuzzy_match(text: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let fold = |c: char| if case_sensitive { c } else { c.to_lowercase().next().unwrap_or(c) };
+    let text: Vec<char> = text.chars().collect();
+    let folded: Vec<char> = text.iter().map(|&c| fold(c)).collect();
+    let query: Vec<char> = query.chars().map(fold).collect();
+    let (n, m) = (text.len(), query.len());
+    if m == 0 {
+        return Some((0, vec![]));
+    }
+    if m > n {
+        return None;
+    }
+    let bonus: Vec<i64> = (0..n)
+        .map(|j| {
+            let boundary = j == 0 || !text[j - 1].is_alphanumeric() || (text[j - 1].is_lowercase() && text[j].is_uppercase());
+            if boundary {
+                BONUS_BOUNDARY
+            } else {
+                0
+            }
+        })
+        .collect();
+
+    // score[i][j]: query[..=i] を合わせ、query[i] が text[j] に合ったときの最高点。from はその1つ前の位置
+    let mut score = vec![vec![NO_MATCH; n]; m];
+    let mut from = vec![vec![0; n]; m];
+    for j in 0..n {
+        if folded[j] == query[0] {
+            score[0][j] = SCORE_MATCH + bonus[j];
+        }
+    }
+    for i in 1..m {
+        // k <= j - 2 のうち score[i - 1][k] + PENALTY_GAP * k が最大のもの(間を飛ばして来る場合)
+        let mut best_gap: Option<(i64, usize)> = None;
+        for j in i..n {
+            if j >= 2 {
+                let k = j - 2;
+                let value = score[i - 1][k] + PENALTY_GAP * k as i64;
+                if score[i - 1][k] > NO_MATCH && best_gap.is_none_or(|(best, _)| value > best) {
+                    best_gap = Some((value, k));
+                }
+            }
+            if folded[j] != query[i] {
+                continue;
+            }
+            let mut best = None;
+            if score[i - 1][j - 1] > NO_MATCH {
+                best = Some((score[i - 1][j - 1] + BONUS_CONSECUTIVE, j - 1));
+            }
+            if let Some((value, k)) = best_gap {
+                let value = value - PENALTY_GAP * (j as i64 - 1) - PENALTY_GAP_OPEN;
+                if best.is_none_or(|(current, _)| value > current) {
+                    best = Some((value, k));
+                }
+            }
+            if let Some((value, k)) = best {
+                score[i][j] = value + SCORE_MATCH + bonus[j];
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let (end, &total) = score[m - 1].iter().enumerate().filter(|(_, &s)| s > NO_MATCH).max_by_key(|(j, &s)| (s, std::cmp::Reverse(*j)))?;
+    let mut positions = vec![end];
+    let mut j = end;
+    for i in (1..m).rev() {
+        j = from[i][j];
+        positions.push(j);
+    }
+    positions.reverse();
+    Some((total, positions))
+}
+
+// タイトル以外で合ったとき、候補の横に出すもの
+pub struct Detail {
+    pub label: &'static str,
+    pub text: String,
+    pub positions: Vec<usize>,
+}
+
+// 検索の候補1つ
+pub struct SearchHit {
+    // todos の中の位置
+    pub index: usize,
+    pub score: i64,
+    // タイトルの中で合った文字の位置
+    pub title: Vec<usize>,
+    pub detail: Option<Detail>,
+}
+
+// 空白で区切った語がどれもタイトル・タグ・内容・サブタスクのどこかに合えば一致
+pub fn match_todo(todo: &Todo, query: &str) -> Option<(i64, Vec<usize>, Option<Detail>)> {
+    let mut total = 0;
+    let mut title = vec![];
+    let mut detail = None;
+    for term in query.split_whitespace() {
+        let (score, field) = best_field(todo, term)?;
+        total += score;
+        match field {
+            Ok(positions) => title.extend(positions),
+            Err(found) => {
+                detail.get_or_insert(found);
+            }
+        }
+    }
+    title.sort_unstable();
+    title.dedup();
+    Some((total, title, detail))
+}
+
+// term が一番よく合った場所。タイトルなら Ok(位置)、それ以外なら Err(Detail)
+fn best_field(todo: &Todo, term: &str) -> Option<(i64, Result<Vec<usize>, Detail>)> {
+    let mut best: Option<(i64, Result<Vec<usize>, Detail>)> = None;
+    let mut consider = |candidate: (i64, Result<Vec<usize>, Detail>)| {
+        if best.as_ref().is_none_or(|(score, _)| candidate.0 > *score) {
+            best = Some(candidate);
+        }
+    };
+    if let Some((score, positions)) = fuzzy_match(&todo.title, term) {
+        consider((score, Ok(positions)));
+    }
+    for tag in &todo.tags {
+        if let Some((score, positions)) = fuzzy_match(tag, term) {
+            consider((score - PENALTY_TAG, Err(Detail { label: "tag", text: tag.clone(), positions })));
+        }
+    }
+    if let Some((score, positions)) = fuzzy_match(&todo.content, term) {
+        let detail = Detail {
+            label: "content",
+            text: todo.content.clone(),
+            positions,
+        };
+        consider((score - PENALTY_CONTENT, Err(detail)));
+    }
+    fn subtasks(todos: &[Todo], term: &str, found: &mut Vec<(i64, Detail)>) {
+        for todo in todos {
+            if let Some((score, positions)) = fuzzy_match(&todo.title, term) {
+                let detail = Detail {
+                    label: "subtask",
+                    text: todo.title.clone(),
+                    positions,
+                };
+                found.push((score - PENALTY_SUBTASK, detail));
+            }
+            subtasks(&todo.subtasks, term, found);
+        }
+    }
+    let mut found = vec![];
+    subtasks(&todo.subtasks, term, &mut found);
+    for (score, detail) in found {
+        consider((score, Err(detail)));
+    }
+    best
+}
+
+pub fn matches_query(todo: &Todo, query: &str) -> bool {
+    match_todo(todo, query).is_some()
+}
+
+// 検索窓の候補と、Enter で一覧を絞り込むときの両方でこれを使う
+// 今のプロジェクトとタグの絞り込みの中から探し、点数の高い順に並べる
+pub fn search_results(todos: &[Todo], view: &ListView, query: &str) -> Vec<SearchHit> {
+    let mut hits: Vec<SearchHit> = todos
         .iter()
-        .filter(|todo| {
-            levenshtein(&todo.title, query) <= threshold || levenshtein(&todo.content, query) <= threshold
+        .enumerate()
+        .filter(|(_, todo)| view.in_project(todo) && view.tags.matches(todo))
+        .filter_map(|(index, todo)| {
+            let (score, title, detail) = match_todo(todo, query)?;
+            Some(SearchHit { index, score, title, detail })
         })
-        .cloned()
-        .collect()
+        .collect();
+    hits.sort_by_key(|hit| std::cmp::Reverse(hit.score));
+    hits
 }
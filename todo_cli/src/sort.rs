@@ -1,7 +1,9 @@
 use crate::todo::Todo;
-use std::cmp::Ordering;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
 pub enum SortMode {
     ByCompletion,
     ByDeadline,
@@ -16,32 +18,17 @@ pub fn cycle_sort_mode(sort_mode: &mut SortMode) {
     }
 }
 
-pub fn sort_todos(todos: &mut Vec<Todo>, mode: SortMode) {
+pub fn sort_todos(todos: &mut [Todo], mode: SortMode) {
     match mode {
         SortMode::ByCompletion => {
-            todos.sort_by(|a, b| a.done.cmp(&b.done));
+            todos.sort_by_key(|todo| todo.done);
         }
         SortMode::ByDeadline => {
-            todos.sort_by(|a, b| {
-                if a.deadline.is_empty() {
-                    Ordering::Greater
-                } else if b.deadline.is_empty() {
-                    Ordering::Less
-                } else {
-                    a.deadline.cmp(&b.deadline)
-                }
-            });
+            // 期限の無いタスクは最後
+            todos.sort_by_key(|todo| (todo.deadline.is_none(), todo.deadline));
         }
         SortMode::ByPriority => {
-            todos.sort_by(|a, b| {
-                let priority_order = |p: &str| match p {
-                    "high" => 0,
-                    "medium" => 1,
-                    "low" => 2,
-                    _ => 3,
-                };
-                priority_order(&a.priority).cmp(&priority_order(&b.priority))
-            });
+            todos.sort_by_key(|todo| todo.priority.rank());
         }
     }
 }
@@ -0,0 +1,261 @@
+use crate::backup::{backup_todos, create_migration_backup};
+use crate::merge::keyed;
+use crate::project::ProjectSettings;
+use crate::store::Storage;
+use crate::todo::{migrate_tasks, StoreError, Todo, FORMAT_VERSION};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+    INSERT OR IGNORE INTO meta (key, value) VALUES ('revision', 0);
+    CREATE TABLE IF NOT EXISTS tasks (
+        id INTEGER PRIMARY KEY,
+        position INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        done INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+";
+
+// タスク1件を1行に入れ、保存時は変わった行だけを INSERT / UPDATE / DELETE する。
+// サブタスクは親の行の data(JSON)に含める。version は書き込むたびに増える revision。
+// data の形式のバージョンは meta の format_version(無ければ 0)。
+// プロジェクトの設定は settings の projects に JSON で入れる
+pub struct SqliteStorage {
+    path: PathBuf,
+    conn: Option<Connection>,
+    // 最後に読み書きした行と設定。差分を取るのに使う
+    rows: Vec<Row>,
+    projects: ProjectSettings,
+}
+
+struct Row {
+    id: i64,
+    position: i64,
+    todo: Todo,
+}
+
+impl SqliteStorage {
+    pub fn new(path: PathBuf) -> Self {
+        SqliteStorage {
+            path,
+            conn: None,
+            rows: vec![],
+            projects: ProjectSettings::default(),
+        }
+    }
+
+    fn conn(&self) -> &Connection {
+        self.conn.as_ref().expect("begin() must be called before using the connection")
+    }
+
+    fn format_version(&self) -> Result<u64, StoreError> {
+        let version: Option<i64> = self
+            .conn()
+            .query_row("SELECT value FROM meta WHERE key = 'format_version'", [], |row| row.get(0))
+            .optional()?;
+        Ok(version.unwrap_or(0) as u64)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // BEGIN IMMEDIATE で書き込みロックを取ってからスキーマを用意する
+    fn begin(&mut self) -> Result<(), StoreError> {
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(&self.path)?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch("BEGIN IMMEDIATE")?;
+        conn.execute_batch(SCHEMA)?;
+        self.conn = Some(conn);
+        Ok(())
+    }
+
+    fn finish(&mut self, commit: bool) -> Result<(), StoreError> {
+        if let Some(conn) = self.conn.take() {
+            conn.execute_batch(if commit { "COMMIT" } else { "ROLLBACK" })?;
+        }
+        Ok(())
+    }
+
+    fn version(&mut self) -> Result<Option<u64>, StoreError> {
+        let revision: i64 = self
+            .conn()
+            .query_row("SELECT value FROM meta WHERE key = 'revision'", [], |row| row.get(0))?;
+        Ok(Some(revision as u64))
+    }
+
+    fn read(&mut self) -> Result<(Vec<Todo>, ProjectSettings), StoreError> {
+        let mut statement = self.conn().prepare("SELECT id, position, data FROM tasks ORDER BY position")?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        drop(statement);
+
+        let mut tasks = Value::Array(
+            rows.iter()
+                .map(|(_, _, data)| serde_json::from_str(data))
+                .collect::<Result<_, _>>()?,
+        );
+        migrate_tasks(&mut tasks, self.format_version()?)?;
+        let todos: Vec<Todo> = serde_json::from_value(tasks)?;
+        self.rows = rows
+            .into_iter()
+            .zip(todos)
+            .map(|((id, position, _), todo)| Row { id, position, todo })
+            .collect();
+        let projects: Option<String> = self
+            .conn()
+            .query_row("SELECT value FROM settings WHERE key = 'projects'", [], |row| row.get(0))
+            .optional()?;
+        self.projects = match projects {
+            Some(projects) => serde_json::from_str(&projects)?,
+            None => ProjectSettings::default(),
+        };
+        Ok((self.rows.iter().map(|row| row.todo.clone()).collect(), self.projects.clone()))
+    }
+
+    // 全行を今の形式で書き直す(行があるときは先にファイルごとバックアップする)
+    fn upgrade(&mut self) -> Result<(), StoreError> {
+        let version = self.format_version()?;
+        if version >= FORMAT_VERSION {
+            return Ok(());
+        }
+        self.read()?;
+        if !self.rows.is_empty() {
+            create_migration_backup(&self.path, version)?;
+        }
+        let conn = self.conn();
+        for Row { id, todo, .. } in &self.rows {
+            conn.execute(
+                "UPDATE tasks SET title = ?1, done = ?2, data = ?3 WHERE id = ?4",
+                params![todo.title, todo.done, serde_json::to_string(todo)?, id],
+            )?;
+        }
+        conn.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('format_version', ?1)",
+            params![FORMAT_VERSION as i64],
+        )?;
+        conn.execute("UPDATE meta SET value = value + 1 WHERE key = 'revision'", [])?;
+        Ok(())
+    }
+
+    fn write(&mut self, todos: &[Todo], projects: &ProjectSettings) -> Result<Option<u64>, StoreError> {
+        let conn = self.conn.as_ref().expect("begin() must be called before using the connection");
+        let old: Vec<Todo> = self.rows.iter().map(|row| row.todo.clone()).collect();
+        if !old.is_empty() {
+            backup_todos(&self.path, &old, &self.projects)?;
+        }
+        let mut existing: HashMap<_, _> = keyed(&old)
+            .into_iter()
+            .zip(&self.rows)
+            .map(|((key, _), row)| (key, row))
+            .collect();
+
+        let keyed_todos = keyed(todos);
+        let matched: Vec<Option<&Row>> = keyed_todos.iter().map(|(key, _)| existing.remove(key)).collect();
+        let positions = assign_positions(&matched.iter().map(|row| row.map(|row| row.position)).collect::<Vec<_>>());
+
+        let mut rows = vec![];
+        for (((_, todo), old), position) in keyed_todos.into_iter().zip(matched).zip(positions) {
+            let id = match old {
+                Some(old) if old.todo != *todo => {
+                    conn.execute(
+                        "UPDATE tasks SET position = ?1, title = ?2, done = ?3, data = ?4 WHERE id = ?5",
+                        params![position, todo.title, todo.done, serde_json::to_string(todo)?, old.id],
+                    )?;
+                    old.id
+                }
+                // 並び順だけ変わった行は data を書き直さない
+                Some(old) => {
+                    if old.position != position {
+                        conn.execute("UPDATE tasks SET position = ?1 WHERE id = ?2", params![position, old.id])?;
+                    }
+                    old.id
+                }
+                None => {
+                    conn.execute(
+                        "INSERT INTO tasks (position, title, done, data) VALUES (?1, ?2, ?3, ?4)",
+                        params![position, todo.title, todo.done, serde_json::to_string(todo)?],
+                    )?;
+                    conn.last_insert_rowid()
+                }
+            };
+            rows.push(Row { id, position, todo: todo.clone() });
+        }
+        for row in existing.into_values() {
+            conn.execute("DELETE FROM tasks WHERE id = ?1", params![row.id])?;
+        }
+
+        if *projects != self.projects {
+            conn.execute(
+                "INSERT OR REPLACE INTO settings (key, value) VALUES ('projects', ?1)",
+                params![serde_json::to_string(projects)?],
+            )?;
+        }
+
+        conn.execute("UPDATE meta SET value = value + 1 WHERE key = 'revision'", [])?;
+        self.rows = rows;
+        self.projects = projects.clone();
+        self.version()
+    }
+}
+
+// 振り直すときの position の間隔。間に入れたり並べ替えたりしても他の行を書き直さずに済むように空けておく
+const POSITION_GAP: i64 = 1024;
+
+// 新しい並びの各行に position を振る。前の position は、前の行より大きい限りそのまま使い、
+// それ以外(追加した行や前に動かした行)は前後の間に入れる。間が足りなければ全部振り直す
+fn assign_positions(previous: &[Option<i64>]) -> Vec<i64> {
+    let mut positions = vec![None; previous.len()];
+    let mut last = -1;
+    for (position, old) in positions.iter_mut().zip(previous) {
+        if let Some(old) = *old {
+            if old > last {
+                *position = Some(old);
+                last = old;
+            }
+        }
+    }
+
+    let mut assigned = Vec::with_capacity(previous.len());
+    let mut lower = -1;
+    let mut start = 0;
+    while start < positions.len() {
+        if let Some(position) = positions[start] {
+            assigned.push(position);
+            lower = position;
+            start += 1;
+            continue;
+        }
+        let end = positions[start..].iter().position(Option::is_some).map_or(positions.len(), |n| start + n);
+        let upper = positions.get(end).copied().flatten().unwrap_or(i64::MAX);
+        let count = (end - start) as i64;
+        // 末尾に足すときは POSITION_GAP ずつ、間に入れるときは均等に空ける
+        let step = if upper == i64::MAX { POSITION_GAP } else { (upper - lower) / (count + 1) };
+        let fits = step > 0 && step.checked_mul(count).and_then(|span| lower.checked_add(span)).is_some_and(|last| last < upper);
+        if !fits {
+            return (1..=previous.len() as i64).map(|n| n * POSITION_GAP).collect();
+        }
+        assigned.extend((1..=count).map(|n| lower + step * n));
+        start = end;
+    }
+    assigned
+}
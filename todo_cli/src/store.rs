@@ -0,0 +1,204 @@
+use crate::json_storage::JsonStorage;
+use crate::merge::{merge, Conflict, Merge};
+use crate::project::ProjectSettings;
+use crate::sqlite_storage::SqliteStorage;
+use crate::todo::{StoreError, Todo};
+use std::path::{Path, PathBuf};
+
+// 保存先の実装。読み書きの間は begin/finish で他のプロセスを締め出す
+pub trait Storage {
+    fn path(&self) -> &Path;
+    fn begin(&mut self) -> Result<(), StoreError>;
+    fn finish(&mut self, commit: bool) -> Result<(), StoreError>;
+    // 誰かが書き込むたびに変わる値(まだ何も保存されていなければ None)
+    fn version(&mut self) -> Result<Option<u64>, StoreError>;
+    // 古い形式なら読み込み時に変換する
+    fn read(&mut self) -> Result<(Vec<Todo>, ProjectSettings), StoreError>;
+    // 保存されている形式が古ければ、バックアップを取ってから今の形式で書き直す
+    fn upgrade(&mut self) -> Result<(), StoreError>;
+    // 保存して新しい version を返す
+    fn write(&mut self, todos: &[Todo], projects: &ProjectSettings) -> Result<Option<u64>, StoreError>;
+}
+
+pub fn is_sqlite_path(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("db" | "sqlite" | "sqlite3")
+    )
+}
+
+// 拡張子が .db / .sqlite なら SQLite、それ以外は JSON
+pub fn open_storage(path: PathBuf) -> Box<dyn Storage> {
+    if is_sqlite_path(&path) {
+        Box::new(SqliteStorage::new(path))
+    } else {
+        Box::new(JsonStorage::new(path))
+    }
+}
+
+pub enum SaveOutcome {
+    Saved,
+    // 他のプロセスの変更を取り込んでから保存した
+    Merged,
+    // 両方で編集されたタスクがあるので、ユーザーに選んでもらうまで保存しない
+    Conflicts,
+}
+
+struct PendingMerge {
+    merge: Merge,
+    theirs: Vec<Todo>,
+    version: Option<u64>,
+    keep_mine: Vec<bool>,
+}
+
+// 複数の ltd が同じ保存先を使っても上書きし合わないようにする。
+// 最後に読み書きした内容を覚えておき、保存時に保存先が変わっていたら3方向マージする
+pub struct Store {
+    storage: Box<dyn Storage>,
+    base: Vec<Todo>,
+    base_version: Option<u64>,
+    pending: Option<PendingMerge>,
+    // 次に保存するプロジェクトの設定と、最後に読み書きしたもの
+    projects: ProjectSettings,
+    base_projects: ProjectSettings,
+}
+
+impl Store {
+    pub fn new(path: PathBuf) -> Self {
+        Store::with_storage(open_storage(path))
+    }
+
+    pub fn with_storage(storage: Box<dyn Storage>) -> Self {
+        Store {
+            storage,
+            base: vec![],
+            base_version: None,
+            pending: None,
+            projects: ProjectSettings::default(),
+            base_projects: ProjectSettings::default(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.storage.path()
+    }
+
+    pub fn load(&mut self) -> Result<Vec<Todo>, StoreError> {
+        self.locked(|store| {
+            store.storage.upgrade()?;
+            let version = store.storage.version()?;
+            let (todos, projects) = store.storage.read()?;
+            store.base = todos.clone();
+            store.base_version = version;
+            store.pending = None;
+            store.base_projects = projects.clone();
+            store.projects = projects;
+            Ok(todos)
+        })
+    }
+
+    pub fn projects(&self) -> &ProjectSettings {
+        &self.projects
+    }
+
+    // 次の保存でタスクと一緒に書く
+    pub fn set_projects(&mut self, projects: ProjectSettings) {
+        self.projects = projects;
+    }
+
+    pub fn save(&mut self, todos: &mut Vec<Todo>) -> Result<SaveOutcome, StoreError> {
+        if self.pending.is_some() {
+            return Ok(SaveOutcome::Conflicts);
+        }
+        self.locked(|store| {
+            let version = store.storage.version()?;
+            let mut outcome = SaveOutcome::Saved;
+            if version != store.base_version {
+                let (theirs, their_projects) = store.storage.read()?;
+                // プロジェクトの設定は手元で変えていなければ向こうのものを使う
+                if store.projects == store.base_projects {
+                    store.projects = their_projects.clone();
+                }
+                store.base_projects = their_projects;
+                let merged = merge(&store.base, todos, &theirs);
+                if !merged.conflicts.is_empty() {
+                    store.pending = Some(PendingMerge {
+                        merge: merged,
+                        theirs,
+                        version,
+                        keep_mine: vec![],
+                    });
+                    return Ok(SaveOutcome::Conflicts);
+                }
+                *todos = merged.todos;
+                outcome = SaveOutcome::Merged;
+            }
+            store.base_version = store.storage.write(todos, &store.projects)?;
+            store.base = todos.clone();
+            store.base_projects = store.projects.clone();
+            Ok(outcome)
+        })
+    }
+
+    // 手元に未保存の変更が無いときだけ、他のプロセスが書いた内容をそのまま読み直す
+    pub fn reload_if_changed(&mut self, todos: &mut Vec<Todo>) -> Result<bool, StoreError> {
+        if self.pending.is_some() || *todos != self.base || self.projects != self.base_projects {
+            return Ok(false);
+        }
+        self.locked(|store| {
+            let version = store.storage.version()?;
+            if version == store.base_version {
+                return Ok(false);
+            }
+            let (theirs, projects) = store.storage.read()?;
+            store.base = theirs.clone();
+            store.base_version = version;
+            store.base_projects = projects.clone();
+            store.projects = projects;
+            *todos = theirs;
+            Ok(true)
+        })
+    }
+
+    pub fn current_conflict(&self) -> Option<(usize, usize, &Conflict)> {
+        let pending = self.pending.as_ref()?;
+        let position = pending.keep_mine.len();
+        let conflict = pending.merge.conflicts.get(position)?;
+        Some((position + 1, pending.merge.conflicts.len(), conflict))
+    }
+
+    // 衝突を1件ずつ解決し、全部決まったらマージ結果を保存する
+    pub fn resolve_conflict(&mut self, keep_mine: bool, todos: &mut Vec<Todo>) -> Result<Option<SaveOutcome>, StoreError> {
+        let Some(pending) = self.pending.as_mut() else {
+            return Ok(None);
+        };
+        pending.keep_mine.push(keep_mine);
+        if pending.keep_mine.len() < pending.merge.conflicts.len() {
+            return Ok(None);
+        }
+
+        let pending = self.pending.take().unwrap();
+        let mut merged = pending.merge.todos;
+        for (conflict, keep_mine) in pending.merge.conflicts.into_iter().zip(pending.keep_mine).rev() {
+            match if keep_mine { conflict.mine } else { conflict.theirs } {
+                Some(todo) => merged[conflict.index] = todo,
+                None => {
+                    merged.remove(conflict.index);
+                }
+            }
+        }
+        self.base = pending.theirs;
+        self.base_version = pending.version;
+        *todos = merged;
+        self.save(todos).map(Some)
+    }
+
+    fn locked<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, StoreError>) -> Result<T, StoreError> {
+        self.storage.begin()?;
+        let result = f(self);
+        let finished = self.storage.finish(result.is_ok());
+        let value = result?;
+        finished?;
+        Ok(value)
+    }
+}
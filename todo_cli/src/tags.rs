@@ -0,0 +1,131 @@
+use crate::todo::Todo;
+use std::collections::BTreeMap;
+use tui::style::Color;
+
+const CHIP_COLORS: [Color; 8] = [
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::Green,
+    Color::Yellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightRed,
+];
+
+// タグは小文字で持つ。先頭の # は付けない
+pub fn normalize_tag(word: &str) -> Option<String> {
+    let tag = word.trim().trim_start_matches('#').trim_end_matches([',', '.']).to_lowercase();
+    // 「#123」のような番号はタグにしない
+    if tag.is_empty() || tag.chars().all(|c| c.is_ascii_digit()) || tag.contains('#') {
+        return None;
+    }
+    Some(tag)
+}
+
+// タイトル中の #tag を取り出す。戻り値はタグを除いたタイトルとタグ
+pub fn extract_tags(title: &str) -> (String, Vec<String>) {
+    let mut words = vec![];
+    let mut tags = vec![];
+    for word in title.split_whitespace() {
+        match word.strip_prefix('#').and_then(normalize_tag) {
+            Some(tag) => push_tag(&mut tags, tag),
+            None => words.push(word),
+        }
+    }
+    (words.join(" "), tags)
+}
+
+// タグの入力欄。「#work home, urgent」のように # は有っても無くてもよい
+pub fn parse_tags(input: &str) -> Vec<String> {
+    let mut tags = vec![];
+    for tag in input.split([' ', ',']).filter_map(normalize_tag) {
+        push_tag(&mut tags, tag);
+    }
+    tags
+}
+
+pub fn format_tags(tags: &[String]) -> String {
+    tags.iter().map(|tag| format!("#{}", tag)).collect::<Vec<_>>().join(" ")
+}
+
+fn push_tag(tags: &mut Vec<String>, tag: String) {
+    if !tags.contains(&tag) {
+        tags.push(tag);
+    }
+}
+
+// 同じタグはいつも同じ色になるように名前から選ぶ
+pub fn tag_color(tag: &str) -> Color {
+    let hash = tag.bytes().fold(0usize, |hash, byte| hash.wrapping_mul(31).wrapping_add(byte as usize));
+    CHIP_COLORS[hash % CHIP_COLORS.len()]
+}
+
+// 使われているタグと、そのタグが付いたタスクの数(名前順)
+pub fn all_tags<'a>(todos: impl IntoIterator<Item = &'a Todo>) -> Vec<(String, usize)> {
+    fn count<'a>(todos: impl IntoIterator<Item = &'a Todo>, counts: &mut BTreeMap<String, usize>) {
+        for todo in todos {
+            for tag in &todo.tags {
+                *counts.entry(tag.clone()).or_default() += 1;
+            }
+            count(&todo.subtasks, counts);
+        }
+    }
+    let mut counts = BTreeMap::new();
+    count(todos, &mut counts);
+    counts.into_iter().collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum TagMatch {
+    // どれか1つでも付いていれば表示する
+    #[default]
+    Any,
+    // 選んだタグが全部付いているものだけ表示する
+    All,
+}
+
+// タグのサイドバーで選んだ絞り込み
+#[derive(Default)]
+pub struct TagFilter {
+    pub selected: Vec<String>,
+    pub mode: TagMatch,
+}
+
+impl TagFilter {
+    pub fn is_active(&self) -> bool {
+        !self.selected.is_empty()
+    }
+
+    pub fn toggle(&mut self, tag: &str) {
+        match self.selected.iter().position(|selected| selected == tag) {
+            Some(index) => {
+                self.selected.remove(index);
+            }
+            None => self.selected.push(tag.to_string()),
+        }
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            TagMatch::Any => TagMatch::All,
+            TagMatch::All => TagMatch::Any,
+        };
+    }
+
+    // サブタスクに付いたタグも親のタスクのタグとして扱う
+    pub fn matches(&self, todo: &Todo) -> bool {
+        if !self.is_active() {
+            return true;
+        }
+        let has = |tag: &String| has_tag(todo, tag);
+        match self.mode {
+            TagMatch::Any => self.selected.iter().any(has),
+            TagMatch::All => self.selected.iter().all(has),
+        }
+    }
+}
+
+fn has_tag(todo: &Todo, tag: &str) -> bool {
+    todo.tags.iter().any(|t| t == tag) || todo.subtasks.iter().any(|subtask| has_tag(subtask, tag))
+}
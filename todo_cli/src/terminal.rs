@@ -0,0 +1,53 @@
+use crossterm::cursor::Show;
+use crossterm::terminal::{disable_raw_mode, LeaveAlternateScreen};
+use crossterm::ExecutableCommand;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+use std::io;
+use std::panic;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+
+// 終了コード
+//   0: 正常に終了
+//   1: エラー(サブコマンドや設定ファイルのエラー、TUI で保存先が読めずに終えたとき)
+//   2: 引数の書き間違い(clap が終わらせる)
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+// TUI で保存先が読めずに終えたとき。スクリプトからは他のエラーと区別しない
+pub const EXIT_LOAD_FAILED: i32 = EXIT_ERROR;
+
+// raw mode と代替画面を元に戻す。パニック中からも呼ぶのでエラーは無視する
+pub fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let mut stdout = io::stdout();
+    let _ = stdout.execute(LeaveAlternateScreen);
+    let _ = stdout.execute(Show);
+}
+
+pub fn exit(code: i32) -> ! {
+    restore_terminal();
+    std::process::exit(code);
+}
+
+// パニックメッセージは端末を戻してから表示しないと読めない
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+}
+
+// 受け取ったシグナル番号が入る(0なら未受信)。メインループが拾って終了処理をする
+pub fn register_signals() -> io::Result<Arc<AtomicUsize>> {
+    let received = Arc::new(AtomicUsize::new(0));
+    for signal in [SIGTERM, SIGHUP, SIGINT, SIGQUIT] {
+        signal_hook::flag::register_usize(signal, Arc::clone(&received), signal as usize)?;
+    }
+    Ok(received)
+}
+
+// シェルの慣習どおり 128 + シグナル番号で終了する
+pub fn signal_exit_code(signal: usize) -> i32 {
+    128 + signal as i32
+}
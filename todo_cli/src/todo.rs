@@ -1,44 +1,70 @@
+use crate::deadline::parse_absolute_deadline;
+use crate::priority::Priority;
+use crate::project::ProjectSettings;
+use crate::recurrence::{Completion, Recurrence};
 use serde::{Deserialize, Serialize};
-use std::fs;
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
 use std::path::Path;
+use ulid::Ulid;
 use chrono::prelude::*;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Todo {
+    // 作成時に振られるULID。ID無しの古いファイルは読み込み時に位置と中身から振って保存される
+    #[serde(default)]
+    pub id: String,
     pub title: String,
     #[serde(default)]
     pub content: String,
-    #[serde(default = "default_priority")]
-    pub priority: String,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // 親のタスクだけが持つ。空なら Inbox
+    #[serde(default)]
+    pub project: String,
     #[serde(default = "default_date_time")]
     pub date_time: String,
     #[serde(default)]
-    pub deadline: String,
+    pub deadline: Option<DateTime<Utc>>,
     #[serde(default)]
     pub done: bool,
     #[serde(default)]
     pub subtasks: Vec<Todo>,
     #[serde(default)]
     pub expanded: bool,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    // 繰り返しのタスクを完了にした回の記録
+    #[serde(default)]
+    pub completions: Vec<Completion>,
 }
 
 impl Default for Todo {
     fn default() -> Self {
         Todo {
+            id: new_id(),
             title: String::new(),
             content: String::new(),
-            priority: default_priority(),
+            priority: Priority::default(),
+            tags: vec![],
+            project: String::new(),
             date_time: default_date_time(),
-            deadline: String::new(),
+            deadline: None,
             done: false,
             subtasks: vec![],
             expanded: false,
+            recurrence: None,
+            completions: vec![],
         }
     }
 }
 
-fn default_priority() -> String {
-    "low".to_string()
+pub fn new_id() -> String {
+    Ulid::new().to_string()
 }
 
 fn default_date_time() -> String {
@@ -46,44 +72,289 @@ fn default_date_time() -> String {
 }
 
 impl Todo {
-    pub fn new(title: String, content: String, priority: String, deadline: String) -> Self {
+    pub fn new(title: String, content: String, priority: Priority, deadline: Option<DateTime<Utc>>) -> Self {
         Todo {
+            id: new_id(),
             title,
             content,
             priority,
+            tags: vec![],
+            project: String::new(),
             date_time: Utc::now().to_rfc3339(),
             deadline,
             done: false,
             subtasks: vec![],
             expanded: false,
+            recurrence: None,
+            completions: vec![],
         }
     }
 
     pub fn add_subtask(&mut self, title: String) {
-        self.subtasks.push(Todo::new(title, String::new(), "low".to_string(), String::new()));
+        self.subtasks.push(Todo::new(title, String::new(), Priority::Low, None));
+    }
+
+    // 繰り返しのタスクは完了にせず、この回を記録して期限を次の回へ進める(サブタスクも未完了に戻す)。
+    // 次の回が無ければ普通に完了にする。戻り値は進めた先の期限
+    pub fn toggle_done(&mut self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let next = match &self.recurrence {
+            Some(recurrence) if !self.done => recurrence.next_deadline(self.deadline, now),
+            _ => None,
+        };
+        match next {
+            Some(next) => {
+                self.completions.push(Completion {
+                    deadline: self.deadline,
+                    done_at: now,
+                });
+                self.deadline = Some(next);
+                for subtask in &mut self.subtasks {
+                    subtask.done = false;
+                }
+                Some(next)
+            }
+            None => {
+                self.done = !self.done;
+                None
+            }
+        }
     }
 
+    // サブタスクの達成率の平均。サブタスクの下の段も同じように数える
     pub fn completion_rate(&self) -> f32 {
         if self.subtasks.is_empty() {
             return if self.done { 100.0 } else { 0.0 };
         }
-        let completed_subtasks = self.subtasks.iter().filter(|t| t.done).count() as f32;
-        (completed_subtasks / self.subtasks.len() as f32) * 100.0
+        let total: f32 = self.subtasks.iter().map(Todo::completion_rate).sum();
+        total / self.subtasks.len() as f32
+    }
+}
+
+pub fn find_todo<'a>(todos: &'a [Todo], id: &str) -> Option<&'a Todo> {
+    todos.iter().find_map(|todo| {
+        if todo.id == id {
+            Some(todo)
+        } else {
+            find_todo(&todo.subtasks, id)
+        }
+    })
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Sqlite(rusqlite::Error),
+    // 新しい ltd が書いたファイルは読めない
+    UnsupportedVersion(u64),
+}
+
+impl StoreError {
+    // JSONの構文エラーなら問題の行番号(1始まり)
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            StoreError::Json(e) if e.line() > 0 => Some(e.line()),
+            _ => None,
+        }
     }
 }
 
-const DB_FILE: &str = "todos.json";
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => write!(f, "{}", e),
+            StoreError::Json(e) => write!(f, "invalid JSON: {}", e),
+            StoreError::Sqlite(e) => write!(f, "SQLite error: {}", e),
+            StoreError::UnsupportedVersion(version) => write!(
+                f,
+                "format version {} is newer than this ltd supports ({})",
+                version, FORMAT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Json(e)
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Sqlite(e)
+    }
+}
+
+// 保存形式のバージョン。形式を変えるときは上げて MIGRATIONS に変換を足す
+pub const FORMAT_VERSION: u64 = 4;
+
+// MIGRATIONS[n] はバージョン n のタスク一覧を n + 1 に上げる
+const MIGRATIONS: [fn(&mut Value); FORMAT_VERSION as usize] = [
+    // 0 -> 1: IDの無いタスクにIDを振る
+    migrate_assign_ids,
+    // 1 -> 2: 優先度を小文字の既知の値に揃える(知らない値は none)
+    migrate_normalize_priorities,
+    // 2 -> 3: 期限を文字列から日時にする
+    migrate_parse_deadlines,
+    // 3 -> 4: タスクはそのまま。ファイルにプロジェクトの設定を持つようになった
+    migrate_add_project_settings,
+];
+
+fn migrate_assign_ids(tasks: &mut Value) {
+    assign_ids(tasks, "");
+}
 
-pub fn load_todos() -> Vec<Todo> {
-    if Path::new(DB_FILE).exists() {
-        let data = fs::read_to_string(DB_FILE).expect("Unable to read file");
-        serde_json::from_str(&data).expect("Unable to parse JSON")
+fn assign_ids(tasks: &mut Value, parent: &str) {
+    for (index, task) in tasks.as_array_mut().into_iter().flatten().enumerate() {
+        let has_id = task.get("id").and_then(Value::as_str).is_some_and(|id| !id.is_empty());
+        if !has_id {
+            task["id"] = json!(derived_id(&format!("{}/{}", parent, index), task));
+        }
+        let id = task["id"].as_str().unwrap_or_default().to_string();
+        if let Some(subtasks) = task.get_mut("subtasks") {
+            assign_ids(subtasks, &id);
+        }
+    }
+}
+
+// 書き戻す前に何度読んでも同じIDになるように、一覧の中の位置と中身から作る。
+// 時刻の部分は作成日時(読めなければ 0)
+fn derived_id(position: &str, task: &Value) -> String {
+    let data = format!("{}\n{}", position, task);
+    let fnv1a = |seed: u64| data.bytes().fold(seed, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    let random = (fnv1a(0xcbf29ce484222325) as u128) << 64 | fnv1a(0x84222325cbf29ce4) as u128;
+    let created = task
+        .get("date_time")
+        .and_then(Value::as_str)
+        .and_then(|date_time| DateTime::parse_from_rfc3339(date_time).ok())
+        .map_or(0, |date_time| date_time.timestamp_millis().max(0) as u64);
+    Ulid::from_parts(created, random).to_string()
+}
+
+fn migrate_normalize_priorities(tasks: &mut Value) {
+    for task in tasks.as_array_mut().into_iter().flatten() {
+        if let Some(priority) = task.get("priority").and_then(Value::as_str) {
+            let priority = Priority::parse(priority).unwrap_or(Priority::None);
+            task["priority"] = json!(priority.to_str());
+        }
+        if let Some(subtasks) = task.get_mut("subtasks") {
+            migrate_normalize_priorities(subtasks);
+        }
+    }
+}
+
+// 日付として読めない期限は消さずに内容の末尾へ移す
+fn migrate_parse_deadlines(tasks: &mut Value) {
+    for task in tasks.as_array_mut().into_iter().flatten() {
+        if let Some(deadline) = task.get("deadline").and_then(Value::as_str).map(str::to_string) {
+            match parse_absolute_deadline(&deadline) {
+                Some(parsed) => task["deadline"] = json!(parsed),
+                None => {
+                    task["deadline"] = Value::Null;
+                    if !deadline.trim().is_empty() {
+                        let content = task.get("content").and_then(Value::as_str).unwrap_or_default();
+                        let note = format!("Deadline: {}", deadline);
+                        task["content"] = json!(if content.is_empty() { note } else { format!("{}\n{}", content, note) });
+                    }
+                }
+            }
+        }
+        if let Some(subtasks) = task.get_mut("subtasks") {
+            migrate_parse_deadlines(subtasks);
+        }
+    }
+}
+
+// タスクの一覧は変えない。バージョンを上げるのは、設定を知らない古い ltd が読んで消してしまわないようにするため
+fn migrate_add_project_settings(_tasks: &mut Value) {}
+
+// バージョン from のタスク一覧(JSONの配列)を今の形式まで1段ずつ上げる
+pub fn migrate_tasks(tasks: &mut Value, from: u64) -> Result<(), StoreError> {
+    if from > FORMAT_VERSION {
+        return Err(StoreError::UnsupportedVersion(from));
+    }
+    for migration in &MIGRATIONS[from as usize..] {
+        migration(tasks);
+    }
+    Ok(())
+}
+
+// ファイルの形式は {"version": n, "todos": [...], "projects": {...}}。バージョンの無いただの配列は 0 とみなす
+pub fn file_version(data: &Value) -> u64 {
+    match data {
+        Value::Object(map) => map.get("version").and_then(Value::as_u64).unwrap_or(0),
+        _ => 0,
+    }
+}
+
+// 古い形式ならメモリ上で変換して読み込む。戻り値の3つ目は元のバージョン
+pub fn decode_todos(data: &[u8]) -> Result<(Vec<Todo>, ProjectSettings, u64), StoreError> {
+    let mut value: Value = serde_json::from_slice(data)?;
+    let version = file_version(&value);
+    let (mut tasks, projects) = match &mut value {
+        Value::Object(map) => (
+            map.remove("todos").unwrap_or_else(|| json!([])),
+            map.remove("projects").unwrap_or_else(|| json!({})),
+        ),
+        other => (other.take(), json!({})),
+    };
+    migrate_tasks(&mut tasks, version)?;
+    Ok((serde_json::from_value(tasks)?, serde_json::from_value(projects)?, version))
+}
+
+#[derive(Serialize)]
+struct TodoFile<'a> {
+    version: u64,
+    todos: &'a [Todo],
+    projects: &'a ProjectSettings,
+}
+
+pub fn encode_todos(todos: &[Todo], projects: &ProjectSettings) -> Result<String, StoreError> {
+    Ok(serde_json::to_string_pretty(&TodoFile {
+        version: FORMAT_VERSION,
+        todos,
+        projects,
+    })?)
+}
+
+pub fn load_todos_from(path: &Path) -> Result<Vec<Todo>, StoreError> {
+    if path.exists() {
+        Ok(decode_todos(&fs::read(path)?)?.0)
     } else {
-        vec![]
+        Ok(vec![])
     }
 }
 
-pub fn save_todos(todos: &Vec<Todo>) {
-    let data = serde_json::to_string_pretty(todos).expect("Unable to serialize");
-    fs::write(DB_FILE, data).expect("Unable to write file");
+// 一時ファイルに書いて fsync してから rename するので、途中で落ちても元のファイルは壊れない
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+
+    let mut tmp_name = path.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = dir.join(tmp_name);
+
+    let result = (|| {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        File::open(dir)?.sync_all()
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
 }
@@ -0,0 +1,282 @@
+use crate::deadline::{format_compact_deadline, parse_absolute_deadline, to_utc};
+use crate::priority::Priority;
+use crate::tags::normalize_tag;
+use crate::todo::Todo;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
+use std::collections::HashMap;
+use ulid::Ulid;
+
+// todo.txt 形式(http://todotxt.org)との変換
+// x 完了日 作成日 (A) タイトル +プロジェクト @コンテキスト due:期限
+// サブタスクは id:(親) と parent:(子) で親子をつなぐ。@コンテキストは ltd のタグになる
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+// (A) が一番高い。E 以降は low として読む
+fn priority_letter(priority: Priority) -> Option<char> {
+    match priority {
+        Priority::Urgent => Some('A'),
+        Priority::High => Some('B'),
+        Priority::Medium => Some('C'),
+        Priority::Low => Some('D'),
+        Priority::None => None,
+    }
+}
+
+fn letter_priority(letter: char) -> Option<Priority> {
+    match letter {
+        'A' => Some(Priority::Urgent),
+        'B' => Some(Priority::High),
+        'C' => Some(Priority::Medium),
+        'D' => Some(Priority::Low),
+        _ => None,
+    }
+}
+
+fn local_date(date_time: &str) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(date_time).ok().map(|date_time| date_time.with_timezone(&Local).date_naive())
+}
+
+// 戻り値は書き出した文字列と、todo.txt に書けなかったものの一覧
+pub fn export(todos: &[Todo]) -> (String, Vec<String>) {
+    fn push(todo: &Todo, parent: Option<&str>, lines: &mut Vec<String>, warnings: &mut Vec<String>) {
+        let mut words = vec![];
+        let created = local_date(&todo.date_time);
+        if todo.done {
+            // ltd は完了にした日を持っていない。todo.txt では x の後の日付は完了日と読まれるので、作成日も書かない
+            words.push("x".to_string());
+            if created.is_some() {
+                warnings.push(format!("\"{}\": completion date is unknown; creation date not exported", todo.title));
+            }
+        } else {
+            if let Some(letter) = priority_letter(todo.priority) {
+                words.push(format!("({})", letter));
+            }
+            if let Some(date) = created {
+                words.push(date.format(DATE_FORMAT).to_string());
+            }
+        }
+        words.push(todo.title.clone());
+        if !todo.project.is_empty() {
+            let project = todo.project.split_whitespace().collect::<Vec<_>>().join("_");
+            if project != todo.project {
+                warnings.push(format!("\"{}\": spaces in project \"{}\" not kept; written as +{}", todo.title, todo.project, project));
+            }
+            words.push(format!("+{}", project));
+        }
+        words.extend(todo.tags.iter().map(|tag| format!("@{}", tag)));
+        if let Some(deadline) = todo.deadline {
+            words.push(format!("due:{}", format_compact_deadline(deadline)));
+        }
+        if todo.done {
+            if let Some(letter) = priority_letter(todo.priority) {
+                words.push(format!("pri:{}", letter));
+            }
+        }
+        if !todo.subtasks.is_empty() {
+            words.push(format!("id:{}", todo.id));
+        }
+        if let Some(parent) = parent {
+            words.push(format!("parent:{}", parent));
+        }
+        if !todo.content.is_empty() {
+            warnings.push(format!("\"{}\": notes are not exported", todo.title));
+        }
+        if let Some(recurrence) = &todo.recurrence {
+            warnings.push(format!("\"{}\": repeat rule \"{}\" is not exported", todo.title, recurrence));
+        }
+        let line = words.join(" ");
+        // タイトルの +word や @word、key:value、行頭の x や (A) は todo.txt の書式として読まれてしまう
+        let read_back = import(&line).0.into_iter().next().map(|read| read.title).unwrap_or_default();
+        if read_back != todo.title {
+            warnings.push(format!("\"{}\": todo.txt syntax in the title not kept; reads back as \"{}\"", todo.title, read_back));
+        }
+        lines.push(line);
+        for subtask in &todo.subtasks {
+            push(subtask, Some(&todo.id), lines, warnings);
+        }
+    }
+
+    let mut lines = vec![];
+    let mut warnings = vec![];
+    for todo in todos {
+        push(todo, None, &mut lines, &mut warnings);
+    }
+    let mut text = lines.join("\n");
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    (text, warnings)
+}
+
+// 読み込んだ1行。id と parent は id: と parent: の値
+struct Line {
+    number: usize,
+    todo: Todo,
+    id: Option<String>,
+    parent: Option<String>,
+}
+
+// 戻り値は読み込んだタスクと、ltd に入れられずにタイトルに残したものや読み飛ばした行の一覧
+pub fn import(text: &str) -> (Vec<Todo>, Vec<String>) {
+    let mut warnings = vec![];
+    let mut parsed: Vec<Line> = vec![];
+    let mut completion_dates = 0;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let mut warn = |message: String| warnings.push(format!("line {}: {}", number, message));
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        let mut todo = Todo::new(String::new(), String::new(), Priority::None, None);
+        let mut rest = &words[..];
+        if rest[0] == "x" {
+            todo.done = true;
+            rest = &rest[1..];
+            if rest.first().and_then(|word| parse_date(word)).is_some() {
+                completion_dates += 1;
+                rest = &rest[1..];
+            }
+        }
+        if let Some(letter) = rest.first().and_then(|word| word.strip_prefix('(')?.strip_suffix(')')?.parse::<char>().ok()) {
+            if letter.is_ascii_uppercase() {
+                todo.priority = letter_to_priority(letter, &mut warn);
+                rest = &rest[1..];
+            }
+        }
+        if let Some(date) = rest.first().and_then(|word| parse_date(word)) {
+            if let Ok(created) = to_utc(date.and_time(NaiveTime::MIN)) {
+                todo.date_time = created.to_rfc3339();
+            }
+            rest = &rest[1..];
+        }
+
+        // +プロジェクトが複数あれば最後のものを使う(書き出すときはタイトルの後ろに付けるので、読み戻しても同じになる)
+        let last_project = rest.iter().rposition(|word| word.len() > 1 && word.starts_with('+'));
+        let mut title = vec![];
+        let (mut id, mut parent) = (None, None);
+        for (position, &word) in rest.iter().enumerate() {
+            if let Some(project) = word.strip_prefix('+').filter(|project| !project.is_empty()) {
+                if Some(position) == last_project {
+                    todo.project = project.to_string();
+                    continue;
+                }
+                warn(format!("only one project per task; kept {} in the title", word));
+            } else if let Some(tag) = word.strip_prefix('@').and_then(normalize_tag) {
+                if !todo.tags.contains(&tag) {
+                    todo.tags.push(tag);
+                }
+                continue;
+            } else if let Some((key, value)) = key_value(word) {
+                match key {
+                    "due" => match parse_absolute_deadline(value) {
+                        Some(deadline) => {
+                            todo.deadline = Some(deadline);
+                            continue;
+                        }
+                        None => warn(format!("can't read {}; kept it in the title", word)),
+                    },
+                    "pri" => match value.parse::<char>() {
+                        Ok(letter) if letter.is_ascii_uppercase() => {
+                            todo.priority = letter_to_priority(letter, &mut warn);
+                            continue;
+                        }
+                        _ => warn(format!("can't read {}; kept it in the title", word)),
+                    },
+                    // ltd が書き出した ID ならそのまま使う
+                    "id" => {
+                        if Ulid::from_string(value).is_ok() {
+                            todo.id = value.to_string();
+                        }
+                        id = Some(value.to_string());
+                        continue;
+                    }
+                    "parent" => {
+                        parent = Some(value.to_string());
+                        continue;
+                    }
+                    _ => warn(format!("ltd has no field for {}; kept it in the title", word)),
+                }
+            }
+            title.push(word);
+        }
+        if title.is_empty() {
+            warn("no title; skipped".to_string());
+            continue;
+        }
+        todo.title = title.join(" ");
+        parsed.push(Line { number, todo, id, parent });
+    }
+
+    // parent: の付いた行を、id: の付いた行の下に入れる。親が見つからなければ親のタスクとして残す
+    let ids: HashMap<String, usize> = parsed.iter().enumerate().filter_map(|(index, line)| Some((line.id.clone()?, index))).collect();
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut roots = vec![];
+    for (index, line) in parsed.iter().enumerate() {
+        match line.parent.as_ref().map(|parent| ids.get(parent).copied()) {
+            Some(Some(parent)) if !is_ancestor(&parsed, &ids, index, parent) => children.entry(parent).or_default().push(index),
+            Some(_) => {
+                warnings.push(format!("line {}: parent of \"{}\" not found; imported it as a task of its own", line.number, line.todo.title));
+                roots.push(index);
+            }
+            None => roots.push(index),
+        }
+    }
+    let mut slots: Vec<Option<Todo>> = parsed.into_iter().map(|line| Some(line.todo)).collect();
+    fn build(index: usize, slots: &mut [Option<Todo>], children: &HashMap<usize, Vec<usize>>, warnings: &mut Vec<String>) -> Option<Todo> {
+        let mut todo = slots[index].take()?;
+        for &child in children.get(&index).into_iter().flatten() {
+            if let Some(mut subtask) = build(child, slots, children, warnings) {
+                if !subtask.project.is_empty() {
+                    warnings.push(format!("\"{}\": subtasks use the project of their parent; +{} not kept", subtask.title, subtask.project));
+                    subtask.project.clear();
+                }
+                todo.subtasks.push(subtask);
+                todo.expanded = true;
+            }
+        }
+        Some(todo)
+    }
+    let todos = roots.into_iter().filter_map(|index| build(index, &mut slots, &children, &mut warnings)).collect();
+    if completion_dates > 0 {
+        warnings.push(format!(
+            "{} completion date{} not kept (ltd only records whether a task is done)",
+            completion_dates,
+            if completion_dates == 1 { " was" } else { "s were" }
+        ));
+    }
+    (todos, warnings)
+}
+
+fn letter_to_priority(letter: char, warn: &mut impl FnMut(String)) -> Priority {
+    letter_priority(letter).unwrap_or_else(|| {
+        warn(format!("priority ({}) read as low; ltd has four levels, (A) to (D)", letter));
+        Priority::Low
+    })
+}
+
+fn parse_date(word: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(word, DATE_FORMAT).ok().filter(|_| word.len() == 10)
+}
+
+// key:value の形。URL(http://...)や時刻(10:30)は含めない
+fn key_value(word: &str) -> Option<(&str, &str)> {
+    let (key, value) = word.split_once(':')?;
+    let is_key = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphabetic());
+    (is_key && !value.is_empty() && !value.starts_with('/')).then_some((key, value))
+}
+
+// index を parent の下に入れると輪になるか
+fn is_ancestor(parsed: &[Line], ids: &HashMap<String, usize>, index: usize, parent: usize) -> bool {
+    let mut current = Some(parent);
+    let mut steps = 0;
+    while let Some(node) = current {
+        if node == index || steps > parsed.len() {
+            return true;
+        }
+        current = parsed[node].parent.as_ref().and_then(|parent| ids.get(parent).copied());
+        steps += 1;
+    }
+    false
+}
@@ -0,0 +1,130 @@
+use crate::project::ListView;
+use crate::todo::Todo;
+
+// タスクの木の中の位置。[2, 0, 1] は 3番目のタスクの 1番目のサブタスクの 2番目のサブタスク
+pub type TreePath = Vec<usize>;
+
+// 一覧の1行
+pub struct Row<'a> {
+    pub path: TreePath,
+    pub todo: &'a Todo,
+    // 祖先の段ごとに、その下にまだ兄弟が続くか(インデントの縦線を引くか)
+    pub guides: Vec<bool>,
+    pub last: bool,
+}
+
+impl Row<'_> {
+    pub fn depth(&self) -> usize {
+        self.path.len() - 1
+    }
+
+    // 「│  ├ 」のようなインデントの線。親のタスクには付かない
+    pub fn indent(&self) -> String {
+        if self.depth() == 0 {
+            return String::new();
+        }
+        let mut indent = String::from("  ");
+        for &more in &self.guides[1..] {
+            indent.push_str(if more { "│  " } else { "   " });
+        }
+        indent.push_str(if self.last { "└ " } else { "├ " });
+        indent
+    }
+}
+
+// 一覧に出る行。絞り込みに合う親のタスクと、展開したタスクの子孫
+pub fn visible_rows<'a>(todos: &'a [Todo], view: &ListView) -> Vec<Row<'a>> {
+    fn push_children<'a>(rows: &mut Vec<Row<'a>>, todo: &'a Todo, path: &TreePath, guides: &[bool]) {
+        if !todo.expanded {
+            return;
+        }
+        for (index, child) in todo.subtasks.iter().enumerate() {
+            let mut child_path = path.clone();
+            child_path.push(index);
+            let last = index + 1 == todo.subtasks.len();
+            rows.push(Row {
+                path: child_path.clone(),
+                todo: child,
+                guides: guides.to_vec(),
+                last,
+            });
+            let mut child_guides = guides.to_vec();
+            child_guides.push(!last);
+            push_children(rows, child, &child_path, &child_guides);
+        }
+    }
+
+    let mut rows = vec![];
+    for (index, todo) in todos.iter().enumerate().filter(|(_, todo)| view.matches(todo)) {
+        let path = vec![index];
+        rows.push(Row {
+            path: path.clone(),
+            todo,
+            guides: vec![],
+            last: true,
+        });
+        push_children(&mut rows, todo, &path, &[true]);
+    }
+    rows
+}
+
+pub fn get<'a>(todos: &'a [Todo], path: &[usize]) -> Option<&'a Todo> {
+    let (first, rest) = path.split_first()?;
+    let todo = todos.get(*first)?;
+    if rest.is_empty() {
+        Some(todo)
+    } else {
+        get(&todo.subtasks, rest)
+    }
+}
+
+pub fn get_mut<'a>(todos: &'a mut [Todo], path: &[usize]) -> Option<&'a mut Todo> {
+    let (first, rest) = path.split_first()?;
+    let todo = todos.get_mut(*first)?;
+    if rest.is_empty() {
+        Some(todo)
+    } else {
+        get_mut(&mut todo.subtasks, rest)
+    }
+}
+
+// path の位置が入っている一覧(親のタスクなら todos 自体)
+pub fn siblings_mut<'a>(todos: &'a mut Vec<Todo>, path: &[usize]) -> Option<&'a mut Vec<Todo>> {
+    match path.split_last() {
+        Some((_, [])) => Some(todos),
+        Some((_, parent)) => Some(&mut get_mut(todos, parent)?.subtasks),
+        None => None,
+    }
+}
+
+pub fn remove(todos: &mut Vec<Todo>, path: &[usize]) -> Option<Todo> {
+    let index = *path.last()?;
+    let siblings = siblings_mut(todos, path)?;
+    (index < siblings.len()).then(|| siblings.remove(index))
+}
+
+// path の位置に入れる。入れる先の一覧より後ろの位置なら末尾に入れる。親が無くて入れられなければ todo を返す
+pub fn insert(todos: &mut Vec<Todo>, path: &[usize], todo: Todo) -> Option<Todo> {
+    let Some(&index) = path.last() else {
+        return Some(todo);
+    };
+    match siblings_mut(todos, path) {
+        Some(siblings) => {
+            siblings.insert(index.min(siblings.len()), todo);
+            None
+        }
+        None => Some(todo),
+    }
+}
+
+pub fn path_of(todos: &[Todo], id: &str) -> Option<TreePath> {
+    todos.iter().enumerate().find_map(|(index, todo)| {
+        if todo.id == id {
+            Some(vec![index])
+        } else {
+            let mut path = path_of(&todo.subtasks, id)?;
+            path.insert(0, index);
+            Some(path)
+        }
+    })
+}
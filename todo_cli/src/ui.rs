@@ -1,28 +1,47 @@
-use crate::input::{InputMode, PrioritySelection};
+use crate::app::App;
+use crate::deadline::{describe_remaining, format_deadline, parse_deadline, Urgency};
+use crate::input::InputMode;
+use crate::priority::Priority;
+use crate::recurrence::Recurrence;
+use crate::search::search_results;
 use crate::sort::SortMode;
-use crate::todo::Todo;
+use crate::store::Store;
+use crate::project::{picker_entries, project_of, ListView, PickerEntry};
+use crate::tags::{all_tags, format_tags, tag_color, TagMatch};
+use crate::todo::{find_todo, StoreError, Todo};
+use crate::tree::visible_rows;
+use chrono::{DateTime, Local, Utc};
+use std::path::Path;
 use tui::backend::Backend;
 use tui::layout::{Constraint, Direction, Layout, Rect};
-use tui::style::{Color, Style};
+use tui::style::{Color, Modifier, Style};
 use tui::text::{Span, Spans};
-use tui::widgets::{Block, Borders, List, ListItem, Paragraph};
+use tui::widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap};
 use tui::Frame;
 use tui::widgets::ListState;
 
-pub fn draw_ui<B: Backend>(
-    f: &mut Frame<B>,
-    state: &mut ListState,
-    search_state: &mut ListState,
-    filtered_todos: &Vec<Todo>,
-    input_mode: &InputMode,
-    input_title: &String,
-    input_content: &String,
-    input_priority: &PrioritySelection,
-    input_deadline: &String,
-    search_query: &String,
-    sort_mode: &SortMode,
-    subtask_state: &mut ListState,
-) {
+pub fn draw_ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let App {
+        ref mut state,
+        ref mut search_state,
+        ref todos,
+        ref input_mode,
+        ref input_title,
+        ref input_content,
+        ref input_priority,
+        ref input_deadline,
+        ref input_recurrence,
+        ref input_tags,
+        ref search_query,
+        ref sort_mode,
+        ref mut subtask_state,
+        ref view,
+        ref mut tag_state,
+        ref status_message,
+        ref load_error,
+        ref store,
+        ..
+    } = *app;
     let size = f.size();
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
@@ -48,10 +67,11 @@ pub fn draw_ui<B: Backend>(
         )
         .split(chunks[0]);
 
-    let _right_chunks = Layout::default()
+    let right_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Length(3), Constraint::Min(1)].as_ref())
         .split(chunks[1]);
+    draw_tag_sidebar(f, &right_chunks, todos, view, tag_state, *input_mode == InputMode::FilteringTags);
 
     let sort_mode_str = match sort_mode {
         SortMode::ByCompletion => "Completion",
@@ -61,54 +81,55 @@ pub fn draw_ui<B: Backend>(
 
     let block = Block::default()
         .borders(Borders::ALL)
-        .title(format!("Lazy Todo - Sort Mode: {}", sort_mode_str));
+        .title(format!(
+            "Lazy Todo - Project: {} - Sort Mode: {} - {}",
+            view.project.as_deref().unwrap_or("All projects"),
+            sort_mode_str,
+            store.path().display()
+        ));
     f.render_widget(block, size);
 
-    let items: Vec<ListItem> = filtered_todos
-        .iter()
-        .enumerate()
-        .flat_map(|(i, todo)| {
-            let mut list_items = vec![];
+    let now = Utc::now();
+    let items: Vec<ListItem> = visible_rows(todos, view)
+        .into_iter()
+        .map(|row| {
+            let todo = row.todo;
             let status = if todo.done {
                 Span::styled("✔", Style::default().fg(Color::Green))
             } else {
                 Span::styled("✘", Style::default().fg(Color::Red))
             };
-            let priority = match todo.priority.as_str() {
-                "low" => Span::styled(" ●", Style::default().fg(Color::Green)),
-                "medium" => Span::styled(" ●", Style::default().fg(Color::Yellow)),
-                "high" => Span::styled(" ●", Style::default().fg(Color::Red)),
-                _ => Span::raw(""),
+            // 子のあるタスクには展開しているかどうかの印を付ける
+            let fold = match (todo.subtasks.is_empty(), todo.expanded) {
+                (true, _) => "",
+                (false, true) => "▾ ",
+                (false, false) => "▸ ",
             };
-            let deadline = if todo.deadline.is_empty() {
+            // 全プロジェクトをまとめて出すときは、どのプロジェクトのタスクかも出す
+            let project = if view.project.is_none() && row.depth() == 0 {
+                Span::styled(format!("[{}] ", project_of(todo)), Style::default().fg(Color::DarkGray))
+            } else {
                 Span::raw("")
+            };
+            let completion_rate = if row.depth() == 0 || !todo.subtasks.is_empty() {
+                Span::raw(format!(" | {:.0}%", todo.completion_rate()))
             } else {
-                Span::raw(format!(" | {}", todo.deadline))
+                Span::raw("")
             };
-            let completion_rate = Span::raw(format!(" | {:.0}%", todo.completion_rate()));
             let content = Spans::from(vec![
+                Span::styled(row.indent(), Style::default().fg(Color::DarkGray)),
+                Span::raw(fold),
                 status,
                 Span::raw(": "),
+                project,
                 Span::raw(&todo.title),
-                priority,
-                deadline,
+                priority_dot(todo.priority),
+                recurrence_mark(todo),
+                deadline_span(todo, now),
                 completion_rate,
             ]);
-            list_items.push(ListItem::new(content).style(Style::default()));
-
-            if todo.expanded {
-                for (j, subtask) in todo.subtasks.iter().enumerate() {
-                    let subtask_status = if subtask.done { "✔" } else { "✘" };
-                    let subtask_content = Spans::from(vec![
-                        Span::raw("  ├ "),
-                        Span::styled(subtask_status, Style::default().fg(if subtask.done { Color::Green } else { Color::Red })),
-                        Span::raw(" "),
-                        Span::raw(&subtask.title),
-                    ]);
-                    list_items.push(ListItem::new(subtask_content).style(Style::default()));
-                }
-            }
-            list_items
+            let content = with_tag_chips(content, &todo.tags);
+            ListItem::new(content).style(deadline_style(todo, now))
         })
         .collect();
 
@@ -122,37 +143,60 @@ pub fn draw_ui<B: Backend>(
         .constraints([Constraint::Min(1), Constraint::Length(3)].as_ref())
         .split(left_chunks[2]);
 
-    let instructions = match *input_mode {
+    let instructions = match input_mode {
         InputMode::Normal => {
-            String::from("q: Quit | a: Add | d: Delete | e: Edit | <space><space>: Search | Enter: Toggle Done")
+            let clear = if view.search.is_empty() { "" } else { "Esc: Clear search | " };
+            format!("{}q: Quit | a: Add | d: Delete | e: Edit | t: Tags | p: Projects | m: Move | A: Add Subtask | h/l: Fold | u/C-r: Undo/Redo | <space><space>: Search | Enter: Toggle Done", clear)
         }
         InputMode::AddingTitle | InputMode::EditingTitle(_) => format!("Enter title: {}", input_title),
         InputMode::AddingContent | InputMode::EditingContent(_) => format!("Enter content: {}", input_content),
+        InputMode::AddingTags | InputMode::EditingTags(_) => format!("Enter tags (e.g. #work #home): {}", input_tags),
+        InputMode::FilteringTags => String::from("j/k: Move | Space: Select tag | m: Match any/all | c: Clear | Esc: Back"),
+        InputMode::SwitchingProject | InputMode::MovingTask(_) => {
+            draw_project_picker(f, chunks[1], todos, view, input_mode);
+            String::from("j/k: Move | Enter: Select | n: New project | Esc: Back")
+        }
+        InputMode::NamingProject(_) => format!("New project name: {}", view.new_project),
         InputMode::AddingPriority | InputMode::EditingPriority(_) => {
-            let priority_symbol = match *input_priority {
-                PrioritySelection::Low => "●",
-                PrioritySelection::Medium => "●",
-                PrioritySelection::High => "●",
-            };
-            let priority_color = input_priority.color();
-            let priorities_list = Paragraph::new(Spans::from(vec![Span::styled(priority_symbol, Style::default().fg(priority_color))]))
+            let priority_label = format!("● {}", input_priority.name());
+            let priorities_list = Paragraph::new(Spans::from(vec![Span::styled(priority_label, Style::default().fg(input_priority.color()))]))
                 .block(Block::default().borders(Borders::ALL).title("Select priority"));
 
             f.render_widget(priorities_list, instructions_chunks[1]);
-            format!("Use j/k to change priority")
+            String::from("Use j/k to change priority")
         },
-        InputMode::AddingDeadline | InputMode::EditingDeadline(_) => format!("Enter deadline: {}", input_deadline),
-        InputMode::ViewingDetails(selected) => {
-            if let Some(todo) = filtered_todos.get(selected) {
+        InputMode::AddingDeadline | InputMode::EditingDeadline(_) => {
+            // 入力中の期限がどう解釈されるかをその場で見せる
+            let preview = match parse_deadline(input_deadline, Local::now()) {
+                Ok(Some(deadline)) => format!(" → {}", format_deadline(deadline)),
+                Ok(None) => " → no deadline".to_string(),
+                Err(_) => " → ?".to_string(),
+            };
+            format!("Enter deadline (e.g. 2024-05-01, tomorrow 17:00, fri, +3d, end of month): {}{}", input_deadline, preview)
+        }
+        InputMode::AddingRecurrence | InputMode::EditingRecurrence(_) => {
+            let preview = match Recurrence::parse(input_recurrence, Local::now().date_naive()) {
+                Ok(Some(recurrence)) => format!(" → {}", recurrence),
+                Ok(None) => " → no repeat".to_string(),
+                Err(_) => " → ?".to_string(),
+            };
+            format!(
+                "Enter repeat (daily, weekly mon,thu, monthly 15, every 3d after completion): {}{}",
+                input_recurrence, preview
+            )
+        }
+        InputMode::ViewingDetails(id) => {
+            if let Some(todo) = find_todo(todos, id) {
                 let status = if todo.done { "✔ Completed" } else { "✘ Not Completed" };
-                let priority = match todo.priority.as_str() {
-                    "low" => "Low",
-                    "medium" => "Medium",
-                    "high" => "High",
-                    _ => "Unknown",
+                let priority = Span::styled(todo.priority.name(), Style::default().fg(todo.priority.color()));
+                let deadline = match todo.deadline {
+                    Some(deadline) => Span::styled(
+                        format!("{} ({})", format_deadline(deadline), describe_remaining(deadline, now)),
+                        deadline_style(todo, now),
+                    ),
+                    None => Span::raw("No Deadline"),
                 };
-                let deadline = if todo.deadline.is_empty() { "No Deadline" } else { &todo.deadline };
-                let details = vec![
+                let mut details = vec![
                     Spans::from(vec![
                         Span::styled("Title: ", Style::default().fg(Color::Cyan)),
                         Span::raw(&todo.title),
@@ -163,87 +207,369 @@ pub fn draw_ui<B: Backend>(
                     ]),
                     Spans::from(vec![
                         Span::styled("Priority: ", Style::default().fg(Color::Cyan)),
-                        Span::raw(priority),
+                        priority,
                     ]),
+                    with_tag_chips(Spans::from(Span::styled("Tags:", Style::default().fg(Color::Cyan))), &todo.tags),
                     Spans::from(vec![
                         Span::styled("Deadline: ", Style::default().fg(Color::Cyan)),
-                        Span::raw(deadline),
+                        deadline,
+                    ]),
+                    Spans::from(vec![
+                        Span::styled("Repeat: ", Style::default().fg(Color::Cyan)),
+                        Span::raw(todo.recurrence.as_ref().map(ToString::to_string).unwrap_or_else(|| "No Repeat".to_string())),
                     ]),
                     Spans::from(vec![
                         Span::styled("Status: ", Style::default().fg(Color::Cyan)),
                         Span::raw(status),
                     ]),
                 ];
+                // 繰り返しのタスクは完了した回を新しい順に出す
+                if !todo.completions.is_empty() {
+                    details.push(Spans::from(Span::styled(
+                        format!("Done {} times:", todo.completions.len()),
+                        Style::default().fg(Color::Cyan),
+                    )));
+                    for completion in todo.completions.iter().rev().take(5) {
+                        details.push(Spans::from(Span::raw(format!("  {}", format_deadline(completion.done_at)))));
+                    }
+                }
+                // 直下のサブタスク。j/k で選んだものに印を付ける
+                if !todo.subtasks.is_empty() {
+                    details.push(Spans::from(Span::styled(
+                        format!("Subtasks ({:.0}%):", todo.completion_rate()),
+                        Style::default().fg(Color::Cyan),
+                    )));
+                    for (index, subtask) in todo.subtasks.iter().enumerate() {
+                        let cursor = if subtask_state.selected() == Some(index) { "> " } else { "  " };
+                        let status = if subtask.done { "✔" } else { "✘" };
+                        let nested = if subtask.subtasks.is_empty() {
+                            String::new()
+                        } else {
+                            format!(" ({} subtasks)", subtask.subtasks.len())
+                        };
+                        details.push(Spans::from(Span::raw(format!("{}{} {}{}", cursor, status, subtask.title, nested))));
+                    }
+                }
                 let details_block = Paragraph::new(details)
                     .block(Block::default().borders(Borders::ALL).title("Details"))
                     .wrap(tui::widgets::Wrap { trim: true });
+                f.render_widget(Clear, chunks[1]);
                 f.render_widget(details_block, chunks[1]);
             }
-            String::from("Press q to go back | <space>s: Add Subtask | Enter: Toggle Subtask Done")
+            String::from("q: Back | <space>: Add Subtask | j/k: Select Subtask | Enter: Toggle Subtask Done | o: Open Subtask | h: Open Parent")
+        }
+        InputMode::Searching => String::from("Type to search | Up/Down: Select | Enter: Filter and jump to task | Esc: Cancel"),
+        InputMode::AddingSubtask(..) => format!("Enter subtask title: {}", input_title),
+        InputMode::LoadFailed => {
+            if let Some(error) = load_error {
+                draw_load_error(f, left_chunks[1], store.path(), error);
+            }
+            String::from("b: Load newest backup | n: Start fresh (keeps broken file) | v: View file | q: Quit")
+        }
+        InputMode::RecoveryView => {
+            draw_recovery_view(f, left_chunks[1], store.path(), load_error.as_ref().and_then(|e| e.line()));
+            String::from("q/Esc: Back")
+        }
+        InputMode::ResolvingConflict => {
+            draw_conflict(f, left_chunks[1], store);
+            String::from("m: Keep my version | t: Keep the other version")
         }
-        InputMode::ViewingSubtaskDetails(_, _) => String::from("Press q to go back | Enter: Toggle Subtask Done"),
-        InputMode::Searching => String::from("Type to search | Enter to filter | Esc to cancel"),
-        InputMode::AddingSubtask(_) => format!("Enter subtask title: {}", input_title),
     };
+    let mut instructions_title = vec![Span::raw("Instructions")];
+    if !status_message.is_empty() {
+        instructions_title.push(Span::styled(format!(" - {}", status_message), Style::default().fg(Color::Red)));
+    }
     let instructions_paragraph = Paragraph::new(instructions)
         .style(Style::default().fg(Color::White).bg(Color::Black))
-        .block(Block::default().borders(Borders::ALL).title("Instructions"));
+        .block(Block::default().borders(Borders::ALL).title(Spans::from(instructions_title)));
     f.render_widget(instructions_paragraph, instructions_chunks[0]);
 
     if *input_mode == InputMode::Searching {
         let search_chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([Constraint::Length(3), Constraint::Length(8)].as_ref())
-            .split(Rect {
-                x: size.width / 2 - 60,
-                y: size.height / 2 - 20,
-                width: 120,
-                height: 25,
-            });
+            .split(popup_rect(size, 120, 25));
 
         let search_input = Paragraph::new(search_query.as_ref())
             .style(Style::default().fg(Color::Yellow))
             .block(Block::default().borders(Borders::ALL).title("Search"));
 
-        let search_items: Vec<ListItem> = filtered_todos
-            .iter()
-            .filter(|todo| {
-                todo.title.contains(search_query) || todo.content.contains(search_query)
-            })
-            .map(|todo| {
+        let search_items: Vec<ListItem> = search_results(todos, view, search_query)
+            .into_iter()
+            .map(|hit| {
+                let todo = &todos[hit.index];
                 let status = if todo.done {
                     Span::styled("✔", Style::default().fg(Color::Green))
                 } else {
                     Span::styled("✘", Style::default().fg(Color::Red))
                 };
-                let priority = match todo.priority.as_str() {
-                    "low" => Span::styled(" ●", Style::default().fg(Color::Green)),
-                    "medium" => Span::styled(" ●", Style::default().fg(Color::Yellow)),
-                    "high" => Span::styled(" ●", Style::default().fg(Color::Red)),
-                    _ => Span::raw(""),
-                };
-                let deadline = if todo.deadline.is_empty() {
-                    Span::raw("")
-                } else {
-                    Span::raw(format!(" | {}", todo.deadline))
-                };
+                let priority = priority_dot(todo.priority);
+                let deadline = deadline_span(todo, now);
                 let completion_rate = Span::raw(format!(" | {:.0}%", todo.completion_rate()));
-                let content = Spans::from(vec![
-                    status,
-                    Span::raw(": "),
-                    Span::raw(&todo.title),
-                    priority,
-                    deadline,
-                    completion_rate,
-                ]);
-                ListItem::new(content).style(Style::default())
+                let mut spans = vec![status, Span::raw(": ")];
+                spans.extend(highlighted(&todo.title, &hit.title, Style::default()));
+                spans.extend([priority, recurrence_mark(todo), deadline, completion_rate]);
+                // タイトル以外で合ったときは、どこで合ったかを横に出す
+                if let Some(detail) = &hit.detail {
+                    let dim = Style::default().fg(Color::DarkGray);
+                    spans.push(Span::styled(format!("  {}: ", detail.label), dim));
+                    spans.extend(highlighted(&detail.text, &detail.positions, dim));
+                }
+                let content = with_tag_chips(Spans::from(spans), &todo.tags);
+                ListItem::new(content).style(deadline_style(todo, now))
             })
             .collect();
         let search_list = List::new(search_items)
             .block(Block::default().borders(Borders::ALL).title("Search Results"))
             .highlight_style(Style::default().bg(Color::Blue));
+        f.render_widget(Clear, search_chunks[1]);
         f.render_stateful_widget(search_list, search_chunks[1], search_state);
 
+        f.render_widget(Clear, search_chunks[0]);
         f.render_widget(search_input, search_chunks[0]);
     }
 }
+
+fn draw_load_error<B: Backend>(f: &mut Frame<B>, area: Rect, path: &Path, error: &StoreError) {
+    let text = vec![
+        Spans::from(vec![
+            Span::styled("Could not load ", Style::default().fg(Color::Red)),
+            Span::raw(path.display().to_string()),
+        ]),
+        Spans::from(Span::raw(error.to_string())),
+        Spans::from(Span::raw("")),
+        Spans::from(Span::raw("b: Load the newest readable backup")),
+        Spans::from(Span::raw("n: Start with an empty list (the broken file is moved aside)")),
+        Spans::from(Span::raw("v: Look at the file around the error")),
+        Spans::from(Span::raw("q: Quit without changing anything")),
+    ];
+    let paragraph = Paragraph::new(text)
+        .block(Block::default().borders(Borders::ALL).title("Load Error"))
+        .wrap(Wrap { trim: true });
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+// 壊れたファイルのエラー行の周辺を行番号付きで表示する
+fn draw_recovery_view<B: Backend>(f: &mut Frame<B>, area: Rect, path: &Path, error_line: Option<usize>) {
+    let data = std::fs::read_to_string(path).unwrap_or_default();
+    let lines: Vec<&str> = data.lines().collect();
+    let height = area.height.saturating_sub(2) as usize;
+    let center = error_line.unwrap_or(1).saturating_sub(1);
+    let start = center.saturating_sub(height / 2).min(lines.len().saturating_sub(height));
+    let text: Vec<Spans> = lines
+        .iter()
+        .enumerate()
+        .skip(start)
+        .take(height)
+        .map(|(i, line)| {
+            let style = if Some(i + 1) == error_line {
+                Style::default().fg(Color::Black).bg(Color::Red)
+            } else {
+                Style::default()
+            };
+            Spans::from(vec![
+                Span::styled(format!("{:>5} ", i + 1), Style::default().fg(Color::DarkGray)),
+                Span::styled(line.to_string(), style),
+            ])
+        })
+        .collect();
+    let paragraph = Paragraph::new(text)
+        .block(Block::default().borders(Borders::ALL).title(format!("Recovery View - {}", path.display())));
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+fn describe_version(label: &str, todo: Option<&Todo>) -> Vec<Spans<'static>> {
+    let heading = Spans::from(Span::styled(label.to_string(), Style::default().fg(Color::Cyan)));
+    let Some(todo) = todo else {
+        return vec![heading, Spans::from(Span::raw("  (deleted)"))];
+    };
+    vec![
+        heading,
+        Spans::from(Span::raw(format!("  Title: {}", todo.title))),
+        Spans::from(Span::raw(format!("  Content: {}", todo.content))),
+        Spans::from(Span::raw(format!("  Priority: {}", todo.priority.name()))),
+        Spans::from(Span::raw(format!("  Tags: {}", format_tags(&todo.tags)))),
+        Spans::from(Span::raw(format!("  Deadline: {}", todo.deadline.map(format_deadline).unwrap_or_default()))),
+        Spans::from(Span::raw(format!("  Done: {}", if todo.done { "yes" } else { "no" }))),
+        Spans::from(Span::raw(format!("  Subtasks: {}", todo.subtasks.len()))),
+    ]
+}
+
+// 同じタスクが別の ltd でも編集されたときに、どちらを残すか選ばせる
+fn draw_conflict<B: Backend>(f: &mut Frame<B>, area: Rect, store: &Store) {
+    let Some((position, total, conflict)) = store.current_conflict() else {
+        return;
+    };
+    let mut text = vec![
+        Spans::from(Span::raw("This task was changed here and in another ltd at the same time.")),
+        Spans::from(Span::raw("")),
+    ];
+    text.extend(describe_version("Mine (m)", conflict.mine.as_ref()));
+    text.push(Spans::from(Span::raw("")));
+    text.extend(describe_version("Other (t)", conflict.theirs.as_ref()));
+    let paragraph = Paragraph::new(text)
+        .block(Block::default().borders(Borders::ALL).title(format!("Conflict {}/{}", position, total)))
+        .wrap(Wrap { trim: false });
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+// 一覧の優先度マーク。none のタスクには付けない
+fn priority_dot(priority: Priority) -> Span<'static> {
+    if priority == Priority::None {
+        Span::raw("")
+    } else {
+        Span::styled(" ●", Style::default().fg(priority.color()))
+    }
+}
+
+// 「 | 2024-05-01 17:00 (in 2d)」。完了したタスクには残り時間を付けない
+fn deadline_span(todo: &Todo, now: DateTime<Utc>) -> Span<'static> {
+    match todo.deadline {
+        Some(deadline) if todo.done => Span::raw(format!(" | {}", format_deadline(deadline))),
+        Some(deadline) => Span::raw(format!(" | {} ({})", format_deadline(deadline), describe_remaining(deadline, now))),
+        None => Span::raw(""),
+    }
+}
+
+// 期限が迫っている未完了のタスクの行の色
+fn deadline_style(todo: &Todo, now: DateTime<Utc>) -> Style {
+    let color = todo
+        .deadline
+        .filter(|_| !todo.done)
+        .and_then(|deadline| Urgency::of(deadline, now).color());
+    match color {
+        Some(color) => Style::default().fg(color),
+        None => Style::default(),
+    }
+}
+
+fn recurrence_mark(todo: &Todo) -> Span<'static> {
+    if todo.recurrence.is_some() {
+        Span::styled(" ↻", Style::default().fg(Color::Cyan))
+    } else {
+        Span::raw("")
+    }
+}
+
+// 行の末尾にタグを色付きのチップとして並べる
+fn with_tag_chips<'a>(mut spans: Spans<'a>, tags: &[String]) -> Spans<'a> {
+    for tag in tags {
+        spans.0.push(Span::raw(" "));
+        spans.0.push(Span::styled(format!(" #{} ", tag), Style::default().fg(Color::Black).bg(tag_color(tag))));
+    }
+    spans
+}
+
+// 右側のタグ一覧。選んだタグには印を付け、上の欄に絞り込みの条件を出す
+fn draw_tag_sidebar<B: Backend>(
+    f: &mut Frame<B>,
+    area: &[Rect],
+    todos: &[Todo],
+    view: &ListView,
+    tag_state: &mut ListState,
+    focused: bool,
+) {
+    let tag_filter = &view.tags;
+    let mode = match tag_filter.mode {
+        TagMatch::Any => "any (OR)",
+        TagMatch::All => "all (AND)",
+    };
+    let mut summary = vec![];
+    if tag_filter.is_active() {
+        summary.push(Span::raw(format!("{}: {} ", mode, format_tags(&tag_filter.selected))));
+    }
+    if !view.search.is_empty() {
+        summary.push(Span::styled(format!("search: \"{}\"", view.search), Style::default().fg(Color::Yellow)));
+        summary.push(Span::styled(" (Esc to clear)", Style::default().fg(Color::DarkGray)));
+    }
+    if summary.is_empty() {
+        summary.push(Span::raw("no filter"));
+    }
+    let summary = Spans::from(summary);
+    let border = if focused { Style::default().fg(Color::Yellow) } else { Style::default() };
+    f.render_widget(
+        Paragraph::new(summary).block(Block::default().borders(Borders::ALL).border_style(border).title("Filter")),
+        area[0],
+    );
+
+    let items: Vec<ListItem> = all_tags(todos.iter().filter(|todo| view.in_project(todo)))
+        .into_iter()
+        .map(|(tag, count)| {
+            let mark = if tag_filter.selected.contains(&tag) { "[x] " } else { "[ ] " };
+            ListItem::new(Spans::from(vec![
+                Span::raw(mark),
+                Span::styled(format!(" #{} ", tag), Style::default().fg(Color::Black).bg(tag_color(&tag))),
+                Span::raw(format!(" {}", count)),
+            ]))
+        })
+        .collect();
+    let mut list = List::new(items).block(Block::default().borders(Borders::ALL).border_style(border).title("Tags (t)"));
+    if focused {
+        list = list.highlight_style(Style::default().bg(Color::Blue));
+        f.render_stateful_widget(list, area[1], tag_state);
+    } else {
+        f.render_widget(list, area[1]);
+    }
+}
+
+// プロジェクトを選ぶ一覧。タスクを移すときは「All projects」を出さない
+fn draw_project_picker<B: Backend>(f: &mut Frame<B>, area: Rect, todos: &[Todo], view: &ListView, input_mode: &InputMode) {
+    let moving = matches!(input_mode, InputMode::MovingTask(_));
+    let items: Vec<ListItem> = picker_entries(todos, &view.created, !moving)
+        .into_iter()
+        .map(|entry| {
+            let line = match entry {
+                PickerEntry::AllProjects => Spans::from(Span::raw(format!("All projects ({})", todos.len()))),
+                PickerEntry::Project(name, count) => {
+                    let current = if view.project.as_ref() == Some(&name) { "* " } else { "  " };
+                    Spans::from(Span::raw(format!("{}{} ({})", current, name, count)))
+                }
+                PickerEntry::NewProject => Spans::from(Span::styled("+ New project", Style::default().fg(Color::Cyan))),
+            };
+            ListItem::new(line)
+        })
+        .collect();
+    let title = if moving { "Move to project" } else { "Projects" };
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().bg(Color::Blue));
+    let mut picker = view.picker.clone();
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut picker);
+}
+
+// 検索で合った文字を強調する。positions は文字単位の位置
+fn highlighted(text: &str, positions: &[usize], style: Style) -> Vec<Span<'static>> {
+    let matched = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+    let mut spans: Vec<Span<'static>> = vec![];
+    let mut current = String::new();
+    let mut current_matched = false;
+    for (i, c) in text.chars().enumerate() {
+        let is_matched = positions.contains(&i);
+        if is_matched != current_matched && !current.is_empty() {
+            let style = if current_matched { matched } else { style };
+            spans.push(Span::styled(std::mem::take(&mut current), style));
+        }
+        current_matched = is_matched;
+        current.push(c);
+    }
+    if !current.is_empty() {
+        spans.push(Span::styled(current, if current_matched { matched } else { style }));
+    }
+    spans
+}
+
+// 画面の上寄りに置く窓。端末が小さいときは画面に収まるように縮める
+fn popup_rect(size: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(size.width);
+    let y = (size.height / 2).saturating_sub(20);
+    Rect {
+        x: (size.width - width) / 2,
+        y,
+        width,
+        height: height.min(size.height - y),
+    }
+}
@@ -0,0 +1,169 @@
+// TUI を開かないサブコマンド。保存先に何が残るかを確かめる
+use chrono::{Duration, Utc};
+use ltd::cli::{ListFormat, TaskFields};
+use ltd::commands::{add_command, done_command, edit_command, list_lines, rm_command, short_id, sub_add_command};
+use ltd::output::{json_value, ndjson, resolve_format, table_lines};
+use ltd::priority::Priority;
+use ltd::project::ListView;
+use ltd::store::Store;
+use ltd::todo::Todo;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use unicode_width::UnicodeWidthStr;
+
+fn store() -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todos.json");
+    (dir, path)
+}
+
+fn saved(path: &Path) -> Vec<Todo> {
+    Store::new(path.to_path_buf()).load().unwrap()
+}
+
+#[test]
+fn add_sub_done_and_rm_by_short_id() {
+    let (_dir, path) = store();
+    let fields = TaskFields {
+        priority: Some("high".to_string()),
+        deadline: Some("2030-01-02".to_string()),
+        ..TaskFields::default()
+    };
+    add_command(&path, "Buy milk #errand", fields).unwrap();
+    add_command(&path, "Call mom", TaskFields::default()).unwrap();
+    let todos = saved(&path);
+    assert_eq!(todos[0].title, "Buy milk");
+    assert_eq!(todos[0].tags, vec!["errand"]);
+    assert_eq!(todos[0].priority, Priority::High);
+    assert!(todos[0].deadline.is_some());
+
+    let id = short_id(&todos[0].id);
+    sub_add_command(&path, &id.to_uppercase(), "Check fridge").unwrap();
+    let sub = short_id(&saved(&path)[0].subtasks[0].id);
+    done_command(&path, &sub).unwrap();
+    assert!(saved(&path)[0].subtasks[0].done);
+
+    let lines = list_lines(&saved(&path), &ListView::default(), false, None);
+    assert!(lines[0].starts_with(&format!("{}  [ ] [Inbox] Buy milk  100%", id)));
+    assert_eq!(lines[1], format!("{}    [x] Check fridge", sub));
+    // --pending は完了したサブタスクを出さないだけで、達成率は変えない
+    let pending = list_lines(&saved(&path), &ListView::default(), true, None);
+    assert_eq!(pending.len(), 2);
+    assert!(pending[0].starts_with(&format!("{}  [ ] [Inbox] Buy milk  100%", id)));
+
+    rm_command(&path, &id).unwrap();
+    let titles: Vec<String> = saved(&path).into_iter().map(|todo| todo.title).collect();
+    assert_eq!(titles, vec!["Call mom"]);
+}
+
+#[test]
+fn unknown_and_ambiguous_ids_are_errors() {
+    let (_dir, path) = store();
+    add_command(&path, "One", TaskFields::default()).unwrap();
+    add_command(&path, "Two", TaskFields::default()).unwrap();
+    assert!(done_command(&path, "zzzzzzzz").unwrap_err().starts_with("no task"));
+    // 空の末尾はどのタスクにも合わない
+    assert!(rm_command(&path, "").is_err());
+    assert_eq!(saved(&path).len(), 2);
+}
+
+#[test]
+fn edit_changes_only_the_given_fields() {
+    let (_dir, path) = store();
+    let fields = TaskFields {
+        content: Some("2 litres".to_string()),
+        deadline: Some("2030-01-02".to_string()),
+        ..TaskFields::default()
+    };
+    add_command(&path, "Buy milk", fields).unwrap();
+    let id = saved(&path)[0].id.clone();
+
+    let fields = TaskFields {
+        deadline: Some("none".to_string()),
+        ..TaskFields::default()
+    };
+    edit_command(&path, &id, Some("Buy oat milk #shop".to_string()), fields, false).unwrap();
+    let todo = &saved(&path)[0];
+    assert_eq!(todo.title, "Buy oat milk");
+    assert_eq!(todo.tags, vec!["shop"]);
+    assert_eq!(todo.content, "2 litres");
+    assert_eq!(todo.deadline, None);
+}
+
+#[test]
+fn done_on_a_recurring_task_moves_the_deadline() {
+    let (_dir, path) = store();
+    let fields = TaskFields {
+        deadline: Some("2030-01-07".to_string()),
+        repeat: Some("daily".to_string()),
+        ..TaskFields::default()
+    };
+    add_command(&path, "Water plants", fields).unwrap();
+    let before = saved(&path)[0].clone();
+    done_command(&path, &before.id).unwrap();
+    let after = &saved(&path)[0];
+    assert!(!after.done);
+    assert_eq!(after.completions.len(), 1);
+    assert!(after.deadline > before.deadline);
+}
+
+#[test]
+fn done_on_a_recurring_task_without_a_next_occurrence_completes_it() {
+    let (_dir, path) = store();
+    let fields = |repeat: &str| TaskFields {
+        repeat: Some(repeat.to_string()),
+        ..TaskFields::default()
+    };
+    assert!(add_command(&path, "Too far", fields("after 700000000w")).is_err());
+    add_command(&path, "Once in a while", fields("after 4294967295d")).unwrap();
+    let before = saved(&path)[0].clone();
+    done_command(&path, &before.id).unwrap();
+    let after = &saved(&path)[0];
+    assert!(after.done);
+    assert!(after.completions.is_empty());
+}
+
+#[test]
+fn json_has_stable_fields_and_computed_values() {
+    let now = Utc::now();
+    let mut todo = Todo::new("Pay rent".to_string(), String::new(), Priority::High, Some(now - Duration::days(1)));
+    todo.add_subtask("Transfer".to_string());
+    todo.add_subtask("Receipt".to_string());
+    todo.subtasks[0].done = true;
+
+    let value = json_value(&todo, false, now);
+    assert_eq!(value["id"], todo.id.as_str());
+    assert_eq!(value["short_id"], short_id(&todo.id).as_str());
+    assert_eq!(value["title"], "Pay rent");
+    assert_eq!(value["priority"], "high");
+    assert_eq!(value["completion_rate"], 50.0);
+    assert_eq!(value["overdue"], true);
+    assert_eq!(value["urgency"], "overdue");
+    assert_eq!(value["subtasks"][0]["completion_rate"], 100.0);
+    assert_eq!(value["subtasks"][1]["urgency"], serde_json::Value::Null);
+    let pending = json_value(&todo, true, now);
+    assert_eq!(pending["completion_rate"], 50.0);
+    assert_eq!(pending["subtasks"].as_array().map(Vec::len), Some(1));
+
+    // NDJSON は親のタスクごとに1行
+    let lines = ndjson(&[todo.clone(), todo], false, now);
+    assert_eq!(lines.len(), 2);
+    assert!(lines.iter().all(|line| serde_json::from_str::<serde_json::Value>(line).is_ok()));
+}
+
+#[test]
+fn table_fits_the_width_and_falls_back_to_plain() {
+    assert_eq!(resolve_format(None, true), ListFormat::Table);
+    assert_eq!(resolve_format(None, false), ListFormat::Plain);
+    assert_eq!(resolve_format(Some(ListFormat::Table), false), ListFormat::Plain);
+    assert_eq!(resolve_format(Some(ListFormat::Json), false), ListFormat::Json);
+
+    let mut todo = Todo::new("Prepare the slides for the quarterly planning meeting".to_string(), String::new(), Priority::Low, None);
+    todo.tags = vec!["work".to_string(), "meeting".to_string()];
+    todo.add_subtask("資料を印刷する".to_string());
+    let lines = table_lines(&[todo], true, false, 60, Utc::now());
+    assert!(lines[0].starts_with("ID      TITLE"));
+    assert_eq!(lines.len(), 3);
+    assert!(lines.iter().all(|line| line.width() <= 60), "{:#?}", lines);
+    assert!(lines[1].contains('…'));
+}
@@ -0,0 +1,7 @@
+// テストで共通に使うもの
+use ltd::priority::Priority;
+use ltd::todo::Todo;
+
+pub fn todo(title: &str) -> Todo {
+    Todo::new(title.to_string(), String::new(), Priority::Low, None)
+}
@@ -0,0 +1,22 @@
+// 設定ファイルの読み込み
+use ltd::config::{load_config, parse_color};
+use std::fs;
+use tui::style::Color;
+
+#[test]
+fn colors_are_names_or_hex() {
+    assert_eq!(parse_color("Light-Blue"), Some(Color::LightBlue));
+    assert_eq!(parse_color("#ff00A0"), Some(Color::Rgb(255, 0, 160)));
+    assert_eq!(parse_color("#ff00"), None);
+    // 6バイトでも ASCII でなければ色ではない(パニックしない)
+    assert_eq!(parse_color("#aébcd"), None);
+}
+
+#[test]
+fn a_bad_color_in_the_config_file_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "[priorities.urgent]\ncolor = \"#aébcd\"\n").unwrap();
+    let error = load_config(&path).err().unwrap();
+    assert!(error.ends_with("unknown color \"#aébcd\""), "{}", error);
+}
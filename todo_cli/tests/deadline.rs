@@ -0,0 +1,51 @@
+// 期限の入力の解釈。今日は 2024-05-01(水)10:00 とする
+use chrono::prelude::*;
+use ltd::deadline::parse_deadline;
+
+fn now() -> DateTime<Local> {
+    Local.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap()
+}
+
+// 解釈した期限をローカル時刻で
+fn parsed(input: &str) -> String {
+    let deadline = parse_deadline(input, now()).unwrap().unwrap();
+    deadline.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
+#[test]
+fn dates_without_a_time_end_the_day() {
+    assert_eq!(parsed("2024-06-10"), "2024-06-10 23:59");
+    assert_eq!(parsed("06/10"), "2024-06-10 23:59");
+    assert_eq!(parsed("today"), "2024-05-01 23:59");
+    assert_eq!(parsed("tomorrow 9am"), "2024-05-02 09:00");
+    assert_eq!(parsed("end of month"), "2024-05-31 23:59");
+    assert_eq!(parsed("17:30"), "2024-05-01 17:30");
+    assert_eq!(parse_deadline("  ", now()), Ok(None));
+}
+
+#[test]
+fn weekdays_and_next() {
+    assert_eq!(parsed("fri"), "2024-05-03 23:59");
+    // 今日と同じ曜日は今日、next を付けると来週
+    assert_eq!(parsed("wed"), "2024-05-01 23:59");
+    assert_eq!(parsed("next wed"), "2024-05-08 23:59");
+    assert_eq!(parsed("next monday at 8:15"), "2024-05-06 08:15");
+}
+
+#[test]
+fn offsets() {
+    assert_eq!(parsed("+3d"), "2024-05-04 23:59");
+    assert_eq!(parsed("+2w 17:00"), "2024-05-15 17:00");
+    assert_eq!(parsed("+1m"), "2024-06-01 23:59");
+    assert_eq!(parsed("in 1 year"), "2025-05-01 23:59");
+    assert_eq!(parsed("+5h"), "2024-05-01 15:00");
+    assert_eq!(parsed("in 30 min"), "2024-05-01 10:30");
+}
+
+#[test]
+fn huge_offsets_are_errors_not_panics() {
+    for input in ["+4294967295h", "+999999999y", "+4294967295w", "+4294967295d", "+4294967295m"] {
+        assert_eq!(parse_deadline(input, now()), Err("deadline is too far in the future".to_string()), "{}", input);
+    }
+    assert!(parse_deadline("someday", now()).is_err());
+}
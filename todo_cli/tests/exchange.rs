@@ -0,0 +1,312 @@
+// ltd import / ltd export で使う形式の変換
+use chrono::{Local, NaiveDate, TimeZone, Utc};
+use ltd::history::History;
+use ltd::ical;
+use ltd::priority::Priority;
+use ltd::recurrence::Recurrence;
+use ltd::markdown;
+use ltd::todotxt;
+
+mod common;
+use common::todo;
+
+fn local(date: &str, time: &str) -> chrono::DateTime<Utc> {
+    let naive = NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap().and_time(time.parse().unwrap());
+    Local.from_local_datetime(&naive).unwrap().with_timezone(&Utc)
+}
+
+#[test]
+fn todotxt_fields_map_onto_todos() {
+    let text = "(A) 2024-05-01 Call Mom +Family @phone @Home due:2024-05-10\nx 2024-05-03 2024-05-02 Pay rent pri:B\n";
+    let (todos, warnings) = todotxt::import(text);
+    assert_eq!(todos.len(), 2);
+
+    assert_eq!(todos[0].title, "Call Mom");
+    assert_eq!(todos[0].priority, Priority::Urgent);
+    assert_eq!(todos[0].project, "Family");
+    assert_eq!(todos[0].tags, vec!["phone", "home"]);
+    assert_eq!(todos[0].deadline, Some(local("2024-05-10", "23:59:00")));
+    assert_eq!(todos[0].date_time, local("2024-05-01", "00:00:00").to_rfc3339());
+    assert!(!todos[0].done);
+
+    assert!(todos[1].done);
+    assert_eq!(todos[1].priority, Priority::High);
+    assert_eq!(todos[1].date_time, local("2024-05-02", "00:00:00").to_rfc3339());
+    // 完了日は持てないので知らせる
+    assert_eq!(warnings, vec!["1 completion date was not kept (ltd only records whether a task is done)"]);
+}
+
+#[test]
+fn todotxt_reports_what_it_cannot_keep() {
+    let text = "(F) Read book +A +B rec:1w see http://example.com at 10:30\nx\nChild parent:missing\n";
+    let (todos, warnings) = todotxt::import(text);
+    assert_eq!(todos[0].title, "Read book +A rec:1w see http://example.com at 10:30");
+    assert_eq!(todos[0].project, "B");
+    assert_eq!(todos[0].priority, Priority::Low);
+    assert_eq!(todos[1].title, "Child");
+    assert_eq!(
+        warnings,
+        vec![
+            "line 1: priority (F) read as low; ltd has four levels, (A) to (D)",
+            "line 1: only one project per task; kept +A in the title",
+            "line 1: ltd has no field for rec:1w; kept it in the title",
+            "line 2: no title; skipped",
+            "line 3: parent of \"Child\" not found; imported it as a task of its own",
+        ]
+    );
+
+    let mut noted = todo("Trip");
+    noted.content = "passport".to_string();
+    let (_, warnings) = todotxt::export(&[noted]);
+    assert_eq!(warnings, vec!["\"Trip\": notes are not exported"]);
+
+    // 読み戻すと変わってしまうものは書き出すときに知らせる。完了日は分からないので書かない
+    let mut marked = todo("x marks +the spot @here due:soon");
+    marked.project = "Big move".to_string();
+    marked.done = true;
+    let (text, warnings) = todotxt::export(&[marked]);
+    assert!(text.starts_with("x x marks"), "{}", text);
+    assert_eq!(
+        warnings,
+        vec![
+            "\"x marks +the spot @here due:soon\": completion date is unknown; creation date not exported",
+            "\"x marks +the spot @here due:soon\": spaces in project \"Big move\" not kept; written as +Big_move",
+            "\"x marks +the spot @here due:soon\": todo.txt syntax in the title not kept; reads back as \"x marks +the spot due:soon\"",
+        ]
+    );
+}
+
+#[test]
+fn todotxt_round_trips_subtasks_and_times() {
+    let mut parent = todo("Move house");
+    parent.project = "Home".to_string();
+    parent.priority = Priority::Medium;
+    parent.tags = vec!["big".to_string()];
+    parent.deadline = Some(local("2030-03-01", "17:30:00"));
+    parent.add_subtask("Pack".to_string());
+    parent.subtasks[0].add_subtask("Books".to_string());
+    parent.subtasks[0].subtasks[0].done = true;
+    let mut none = todo("Someday");
+    none.priority = Priority::None;
+
+    let (text, warnings) = todotxt::export(&[parent.clone(), none]);
+    assert_eq!(warnings, vec!["\"Books\": completion date is unknown; creation date not exported"]);
+    assert!(text.contains("(C) "));
+    assert!(text.contains("due:2030-03-01T17:30"));
+
+    let (todos, warnings) = todotxt::import(&text);
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert_eq!(todos.len(), 2);
+    let moved = &todos[0];
+    assert_eq!(moved.id, parent.id);
+    assert_eq!((moved.title.as_str(), moved.project.as_str(), moved.priority), ("Move house", "Home", Priority::Medium));
+    assert_eq!(moved.tags, parent.tags);
+    assert_eq!(moved.deadline, parent.deadline);
+    assert_eq!(moved.subtasks[0].title, "Pack");
+    assert_eq!(moved.subtasks[0].subtasks[0].title, "Books");
+    assert!(moved.subtasks[0].subtasks[0].done);
+    assert_eq!(todos[1].priority, Priority::None);
+
+    // もう一度書き出すと同じ内容になる
+    assert_eq!(todotxt::export(&todos).0, text);
+}
+
+#[test]
+fn markdown_checklists_nest_by_indent() {
+    let text = "\
+# Weekly sync
+
+Attendees: Ann, Bo
+
+## Action items
+
+- [ ] Send the slides #work !high due:2030-01-10
+  Use the new template
+  - [x] Draft
+  - [ ] Review
+    - [ ] Fix charts due:2030-01-09T15:00
+* [X] Book room
+1. [ ] Order pizza !asap
+";
+    let (todos, warnings) = markdown::import(text);
+    assert_eq!(todos.len(), 3);
+    let slides = &todos[0];
+    assert_eq!((slides.title.as_str(), slides.project.as_str()), ("Send the slides", "Action items"));
+    assert_eq!(slides.tags, vec!["work"]);
+    assert_eq!(slides.priority, Priority::High);
+    assert_eq!(slides.deadline, Some(local("2030-01-10", "23:59:00")));
+    assert_eq!(slides.content, "Use the new template");
+    assert!(slides.subtasks[0].done);
+    assert_eq!(slides.subtasks[1].subtasks[0].deadline, Some(local("2030-01-09", "15:00:00")));
+    assert!(todos[1].done);
+    assert_eq!(todos[2].title, "Order pizza !asap");
+    assert_eq!(
+        warnings,
+        vec!["line 13: unknown priority !asap; kept it in the title", "skipped 1 line that is not a checklist item"]
+    );
+}
+
+#[test]
+fn markdown_round_trips() {
+    let mut parent = todo("Move house");
+    parent.project = "Home".to_string();
+    parent.priority = Priority::None;
+    parent.tags = vec!["big".to_string()];
+    parent.content = "Call the movers\nBook a van".to_string();
+    parent.deadline = Some(local("2030-03-01", "17:30:00"));
+    parent.add_subtask("Pack".to_string());
+    parent.subtasks[0].add_subtask("Books".to_string());
+    parent.subtasks[0].subtasks[0].done = true;
+    let inbox = todo("Call mom");
+
+    let (text, warnings) = markdown::export(&[parent, inbox]);
+    assert!(warnings.is_empty());
+    insta::assert_snapshot!(text, @r"
+    ## Home
+
+    - [ ] Move house #big !none due:2030-03-01T17:30
+      > Call the movers
+      > Book a van
+      - [ ] Pack
+        - [x] Books
+
+    ## Inbox
+
+    - [ ] Call mom
+    ");
+
+    let (todos, warnings) = markdown::import(&text);
+    assert!(warnings.is_empty());
+    assert_eq!(markdown::export(&todos).0, text);
+}
+
+#[test]
+fn markdown_notes_that_look_like_markup_round_trip() {
+    let mut parent = todo("Write the README");
+    parent.content = "## Usage\n- [ ] not a subtask\n\n> quoted\n  indented".to_string();
+    parent.add_subtask("Proofread".to_string());
+    parent.subtasks[0].content = "# also a note".to_string();
+    let (text, _) = markdown::export(&[parent.clone()]);
+
+    let (todos, warnings) = markdown::import(&text);
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert_eq!(todos.len(), 1);
+    assert_eq!(todos[0].project, "Inbox");
+    assert_eq!(todos[0].content, parent.content);
+    assert_eq!(todos[0].subtasks.len(), 1);
+    assert_eq!(todos[0].subtasks[0].content, "# also a note");
+
+    // 手で書いた字下げだけのメモも、見出しに見えればメモとして読む
+    let (todos, _) = markdown::import("- [ ] Plan\n  # agenda\n  - [ ] Book room due:tomorrow\n");
+    assert_eq!(todos[0].content, "# agenda");
+    assert_eq!(todos[0].subtasks[0].title, "Book room due:tomorrow");
+}
+
+#[test]
+fn ical_round_trips_subtasks_and_schedules() {
+    let mut parent = todo("Move house, then rest; done");
+    parent.project = "Home".to_string();
+    parent.priority = Priority::Urgent;
+    parent.tags = vec!["big".to_string()];
+    parent.content = "Call the movers\nBook a van".to_string();
+    parent.deadline = Some(local("2030-03-01", "17:30:00"));
+    parent.add_subtask("Pack".to_string());
+    parent.subtasks[0].done = true;
+    parent.subtasks[0].deadline = Some(local("2030-02-20", "23:59:00"));
+    let mut plants = todo("Water plants");
+    plants.recurrence = Some(Recurrence::Monthly { day: 15 });
+    plants.deadline = Some(local("2030-03-15", "23:59:00"));
+
+    let (text, warnings) = ical::export(&[parent.clone(), plants.clone()]);
+    assert!(warnings.is_empty());
+    assert!(text.contains("SUMMARY:Move house\\, then rest\\; done\r\n"));
+    // 時刻付きの期限は UTC、日の終わりの期限は日付だけで書く
+    assert!(text.contains(&format!("DUE:{}\r\n", parent.deadline.unwrap().format("%Y%m%dT%H%M%SZ"))));
+    assert!(text.contains("DUE;VALUE=DATE:20300220\r\n"));
+    assert!(text.contains(&format!("RELATED-TO;RELTYPE=PARENT:{}\r\n", parent.id)));
+    // 繰り返しは期限と同じ DTSTART から数える
+    assert!(text.contains("DTSTART;VALUE=DATE:20300315\r\nRRULE:FREQ=MONTHLY;BYMONTHDAY=15\r\n"));
+    plants.deadline = None;
+    assert_eq!(ical::export(&[plants]).1, vec!["\"Water plants\": repeat rule \"monthly 15\" is not exported without a deadline"]);
+
+    let (todos, warnings) = ical::import(&text);
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert_eq!(todos.len(), 2);
+    let moved = &todos[0];
+    assert_eq!((moved.id.as_str(), moved.title.as_str()), (parent.id.as_str(), parent.title.as_str()));
+    assert_eq!((moved.project.as_str(), moved.priority), ("Home", Priority::Urgent));
+    assert_eq!((&moved.tags, &moved.content, moved.deadline), (&parent.tags, &parent.content, parent.deadline));
+    assert_eq!(moved.subtasks[0].deadline, parent.subtasks[0].deadline);
+    assert!(moved.subtasks[0].done);
+    assert_eq!(todos[1].recurrence, Some(Recurrence::Monthly { day: 15 }));
+
+    let without_stamps = |text: &str| text.lines().filter(|line| !line.starts_with("DTSTAMP")).collect::<Vec<_>>().join("\n");
+    assert_eq!(without_stamps(&ical::export(&todos).0), without_stamps(&text));
+}
+
+#[test]
+fn ical_reads_times_in_every_time_zone_form() {
+    let text = "\
+BEGIN:VCALENDAR\r
+BEGIN:VTODO\r
+SUMMARY:Berlin\r
+DUE;TZID=Europe/Berlin:20300715T120000\r
+CREATED:20300101T080000Z\r
+END:VTODO\r
+BEGIN:VTODO\r
+SUMMARY:New York\r
+DUE;TZID=\"/mozilla.org/20070129_1/America/New_York\":20300115T090000\r
+END:VTODO\r
+BEGIN:VTODO\r
+SUMMARY:Floating\r
+DUE:20300115T090000\r
+PRIORITY:2\r
+LOCATION:Office\r
+END:VTODO\r
+BEGIN:VTODO\r
+SUMMARY:Unknown zone\r
+DUE;TZID=Nowhere:20300115T090000\r
+END:VTODO\r
+BEGIN:VEVENT\r
+SUMMARY:Meeting\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+    let (todos, warnings) = ical::import(text);
+    assert_eq!(todos.len(), 4);
+    // 夏時間(UTC+2)と冬時間(UTC-5)
+    assert_eq!(todos[0].deadline, Some(Utc.with_ymd_and_hms(2030, 7, 15, 10, 0, 0).unwrap()));
+    assert_eq!(todos[0].date_time, Utc.with_ymd_and_hms(2030, 1, 1, 8, 0, 0).unwrap().to_rfc3339());
+    assert_eq!(todos[1].deadline, Some(Utc.with_ymd_and_hms(2030, 1, 15, 14, 0, 0).unwrap()));
+    // タイムゾーンの無い時刻はローカル時刻
+    assert_eq!(todos[2].deadline, Some(local("2030-01-15", "09:00:00")));
+    assert_eq!(todos[2].priority, Priority::High);
+    assert_eq!(todos[3].deadline, Some(local("2030-01-15", "09:00:00")));
+    assert_eq!(
+        warnings,
+        vec![
+            "unknown time zone \"Nowhere\"; read DUE as local time",
+            "skipped 1 VEVENT (only VTODO is read)",
+            "ltd has no field for LOCATION; not kept",
+        ]
+    );
+}
+
+#[test]
+fn an_import_is_undone_in_one_step() {
+    let mut history = History::new(100, None);
+    let mut todos = vec![];
+    history.insert(&mut todos, &[0], todo("Mine"), "Add");
+    let (imported, _) = todotxt::import("Buy milk\nCall mom\n");
+    history.append(&mut todos, imported, "Import 2 tasks".to_string());
+    assert_eq!(todos.len(), 3);
+
+    // 読み込む前からある履歴は残り、読み込んだ分は1回で消える
+    assert_eq!(history.undo(&mut todos).map(|(label, _)| label), Some("Import 2 tasks".to_string()));
+    assert_eq!(todos.iter().map(|todo| todo.title.as_str()).collect::<Vec<_>>(), vec!["Mine"]);
+    history.redo(&mut todos);
+    assert_eq!(todos.len(), 3);
+    history.undo(&mut todos);
+    history.undo(&mut todos);
+    assert!(todos.is_empty());
+}
@@ -0,0 +1,107 @@
+// 複数の ltd が同じ保存先に書いたときの3方向マージ
+use ltd::merge::merge;
+use ltd::store::{SaveOutcome, Store};
+use ltd::todo::Todo;
+
+mod common;
+use common::todo;
+
+fn edited(todo: &Todo, title: &str) -> Todo {
+    let mut todo = todo.clone();
+    todo.title = title.to_string();
+    todo
+}
+
+fn titles(todos: &[Todo]) -> Vec<&str> {
+    todos.iter().map(|todo| todo.title.as_str()).collect()
+}
+
+#[test]
+fn edits_to_different_tasks_are_both_kept() {
+    let (a, b, c) = (todo("A"), todo("B"), todo("C"));
+    let base = vec![a.clone(), b.clone(), c.clone()];
+    let mine = vec![edited(&a, "A mine"), b.clone(), c.clone(), todo("New mine")];
+    // 他のプロセスは B を書き換え、C を消した
+    let theirs = vec![a.clone(), edited(&b, "B theirs"), todo("New theirs")];
+
+    let merged = merge(&base, &mine, &theirs);
+    assert!(merged.conflicts.is_empty());
+    assert_eq!(titles(&merged.todos), vec!["A mine", "B theirs", "New theirs", "New mine"]);
+}
+
+#[test]
+fn the_same_edit_on_both_sides_is_not_a_conflict() {
+    let a = todo("A");
+    let base = vec![a.clone()];
+    let merged = merge(&base, &[edited(&a, "A2")], &[edited(&a, "A2")]);
+    assert!(merged.conflicts.is_empty());
+    assert_eq!(titles(&merged.todos), vec!["A2"]);
+}
+
+#[test]
+fn different_edits_to_the_same_task_conflict() {
+    let (a, b) = (todo("A"), todo("B"));
+    let merged = merge(&[a.clone(), b.clone()], &[edited(&a, "A mine"), b.clone()], &[edited(&a, "A theirs"), b.clone()]);
+    assert_eq!(merged.conflicts.len(), 1);
+    let conflict = &merged.conflicts[0];
+    assert_eq!(conflict.mine.as_ref().map(|todo| todo.title.as_str()), Some("A mine"));
+    assert_eq!(conflict.theirs.as_ref().map(|todo| todo.title.as_str()), Some("A theirs"));
+    // 決まるまでは theirs の内容で置いておく
+    assert_eq!(titles(&merged.todos), vec!["A theirs", "B"]);
+}
+
+#[test]
+fn delete_against_edit_conflicts() {
+    let a = todo("A");
+    let base = vec![a.clone()];
+    // 自分が消して、他のプロセスが書き換えた
+    let merged = merge(&base, &[], &[edited(&a, "A theirs")]);
+    assert_eq!(merged.conflicts.len(), 1);
+    assert!(merged.conflicts[0].mine.is_none());
+    assert_eq!(merged.conflicts[0].theirs.as_ref().map(|todo| todo.title.as_str()), Some("A theirs"));
+
+    // 自分が書き換えて、他のプロセスが消した
+    let merged = merge(&base, &[edited(&a, "A mine")], &[]);
+    assert_eq!(merged.conflicts.len(), 1);
+    assert_eq!(merged.conflicts[0].mine.as_ref().map(|todo| todo.title.as_str()), Some("A mine"));
+    assert!(merged.conflicts[0].theirs.is_none());
+
+    // 書き換えていない方の削除はそのまま通る
+    let merged = merge(&base, &base, &[]);
+    assert!(merged.conflicts.is_empty());
+    assert!(merged.todos.is_empty());
+}
+
+#[test]
+fn two_stores_on_one_file_merge_and_resolve_conflicts() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todos.json");
+    let mut seed = Store::new(path.clone());
+    seed.load().unwrap();
+    let (a, b) = (todo("A"), todo("B"));
+    assert!(matches!(seed.save(&mut vec![a.clone(), b.clone()]), Ok(SaveOutcome::Saved)));
+
+    let mut first = Store::new(path.clone());
+    let mut second = Store::new(path.clone());
+    let mut first_todos = first.load().unwrap();
+    let mut second_todos = second.load().unwrap();
+
+    first_todos[0].title = "A first".to_string();
+    assert!(matches!(first.save(&mut first_todos), Ok(SaveOutcome::Saved)));
+    second_todos[1].title = "B second".to_string();
+    assert!(matches!(second.save(&mut second_todos), Ok(SaveOutcome::Merged)));
+    assert_eq!(titles(&second_todos), vec!["A first", "B second"]);
+    assert_eq!(titles(&Store::new(path.clone()).load().unwrap()), vec!["A first", "B second"]);
+
+    // 同じタスクを両方で書き換えると、選ぶまで保存しない
+    let mut first_todos = first.load().unwrap();
+    first_todos[0].title = "A again".to_string();
+    assert!(matches!(first.save(&mut first_todos), Ok(SaveOutcome::Saved)));
+    second_todos[0].title = "A mine".to_string();
+    assert!(matches!(second.save(&mut second_todos), Ok(SaveOutcome::Conflicts)));
+    assert_eq!(titles(&Store::new(path.clone()).load().unwrap()), vec!["A again", "B second"]);
+    assert!(second.current_conflict().is_some());
+
+    assert!(matches!(second.resolve_conflict(true, &mut second_todos), Ok(Some(SaveOutcome::Saved))));
+    assert_eq!(titles(&Store::new(path).load().unwrap()), vec!["A mine", "B second"]);
+}
@@ -0,0 +1,48 @@
+// 古い形式のファイルの読み込み
+use ltd::store::Store;
+use ltd::todo::{decode_todos, file_version, FORMAT_VERSION};
+use serde_json::Value;
+use std::fs;
+
+// バージョンも ID も無いころのファイル
+const V0: &str = r#"[
+    {"title": "A", "priority": "High", "date_time": "2023-04-01T09:00:00+00:00", "subtasks": [{"title": "A-1"}]},
+    {"title": "B", "deadline": "2023-05-01"},
+    {"title": "B", "deadline": "2023-05-01"}
+]"#;
+
+fn ids(data: &[u8]) -> Vec<String> {
+    let (todos, _, _) = decode_todos(data).unwrap();
+    todos
+        .iter()
+        .flat_map(|todo| std::iter::once(todo).chain(&todo.subtasks))
+        .map(|todo| todo.id.clone())
+        .collect()
+}
+
+#[test]
+fn ids_assigned_to_old_files_are_stable_and_unique() {
+    let first = ids(V0.as_bytes());
+    assert_eq!(first, ids(V0.as_bytes()));
+    assert_eq!(first.len(), 4);
+    let mut unique = first.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), 4, "{:?}", first);
+}
+
+#[test]
+fn loading_an_old_file_writes_it_back_in_the_current_format() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todos.json");
+    fs::write(&path, V0).unwrap();
+
+    let todos = Store::new(path.clone()).load().unwrap();
+    assert_eq!(todos[0].subtasks[0].title, "A-1");
+    assert!(todos[1].deadline.is_some());
+
+    let saved: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    assert_eq!(file_version(&saved), FORMAT_VERSION);
+    let reloaded = Store::new(path).load().unwrap();
+    assert_eq!(todos, reloaded);
+}
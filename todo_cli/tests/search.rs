@@ -0,0 +1,78 @@
+use ltd::project::ListView;
+use ltd::search::{fuzzy_match, match_todo, search_results};
+use ltd::todo::Todo;
+
+mod common;
+use common::todo;
+
+fn ranked(todos: &[Todo], query: &str) -> Vec<String> {
+    search_results(todos, &ListView::default(), query)
+        .into_iter()
+        .map(|hit| todos[hit.index].title.clone())
+        .collect()
+}
+
+#[test]
+fn matches_characters_in_order_with_gaps() {
+    let (_, positions) = fuzzy_match("Write quarterly report", "wqr").unwrap();
+    assert_eq!(positions, vec![0, 6, 16]);
+    assert!(fuzzy_match("Write quarterly report", "rqw").is_none());
+    assert!(fuzzy_match("abc", "abcd").is_none());
+}
+
+#[test]
+fn long_titles_still_match() {
+    let title = "Prepare the slides for the quarterly planning meeting with the whole team";
+    assert!(fuzzy_match(title, "planning").is_some());
+}
+
+#[test]
+fn smart_case() {
+    assert!(fuzzy_match("Buy Milk", "milk").is_some());
+    assert!(fuzzy_match("Buy milk", "Milk").is_none());
+}
+
+#[test]
+fn prefers_consecutive_and_word_start_matches() {
+    let (word_start, _) = fuzzy_match("report card", "rep").unwrap();
+    let (scattered, _) = fuzzy_match("remote shop", "rep").unwrap();
+    assert!(word_start > scattered);
+
+    // 同じ文字でも単語の頭に合わせる
+    let (_, positions) = fuzzy_match("a_b ab", "ab").unwrap();
+    assert_eq!(positions, vec![4, 5]);
+}
+
+#[test]
+fn results_are_ranked_by_score() {
+    let todos = vec![todo("Remote shopping"), todo("Report card"), todo("Call mom")];
+    assert_eq!(ranked(&todos, "rep"), vec!["Report card", "Remote shopping"]);
+}
+
+#[test]
+fn searches_tags_content_and_subtasks() {
+    let mut tagged = todo("Groceries");
+    tagged.tags = vec!["errand".to_string()];
+    let mut described = todo("Trip");
+    described.content = "book the hotel".to_string();
+    let mut parent = todo("Move house");
+    parent.add_subtask("Pack".to_string());
+    parent.subtasks[0].add_subtask("Bookshelf".to_string());
+    let todos = vec![tagged, described, parent];
+
+    assert_eq!(ranked(&todos, "errand"), vec!["Groceries"]);
+    assert_eq!(ranked(&todos, "hotel"), vec!["Trip"]);
+    assert_eq!(ranked(&todos, "bookshelf"), vec!["Move house"]);
+
+    let (_, title, detail) = match_todo(&todos[2], "shelf").unwrap();
+    assert!(title.is_empty());
+    let detail = detail.unwrap();
+    assert_eq!((detail.label, detail.text.as_str()), ("subtask", "Bookshelf"));
+}
+
+#[test]
+fn every_word_has_to_match() {
+    let todos = vec![todo("Buy milk"), todo("Buy bread"), todo("Milk the cow")];
+    assert_eq!(ranked(&todos, "buy milk"), vec!["Buy milk"]);
+    assert_eq!(ranked(&todos, "").len(), 3);
+}
@@ -0,0 +1,94 @@
+// SQLite の保存先は変わった行だけを書き込む
+use ltd::backup::{list_backups, restore_command};
+use ltd::project::{ProjectSettings, ProjectSortMode};
+use ltd::sort::SortMode;
+use ltd::store::{SaveOutcome, Store};
+use ltd::todo::Todo;
+use rusqlite::Connection;
+
+mod common;
+use common::todo;
+
+fn titles(todos: &[Todo]) -> Vec<&str> {
+    todos.iter().map(|todo| todo.title.as_str()).collect()
+}
+
+// 前回からの UPDATE された行の数
+fn updated_rows(conn: &Connection) -> i64 {
+    let count = conn.query_row("SELECT count(*) FROM updated", [], |row| row.get(0)).unwrap();
+    conn.execute("DELETE FROM updated", []).unwrap();
+    count
+}
+
+#[test]
+fn saving_writes_only_the_changed_rows() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todos.db");
+    let mut store = Store::new(path.clone());
+    store.load().unwrap();
+    let mut todos: Vec<Todo> = ["A", "B", "C", "D"].into_iter().map(todo).collect();
+    assert!(matches!(store.save(&mut todos), Ok(SaveOutcome::Saved)));
+
+    let conn = Connection::open(&path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE updated (id INTEGER);
+         CREATE TRIGGER count_updates AFTER UPDATE ON tasks BEGIN INSERT INTO updated VALUES (new.id); END;",
+    )
+    .unwrap();
+
+    // 先頭を消しても残りの行は書き直さない
+    todos.remove(0);
+    store.save(&mut todos).unwrap();
+    assert_eq!(updated_rows(&conn), 0);
+
+    todos[1].title = "C2".to_string();
+    store.save(&mut todos).unwrap();
+    assert_eq!(updated_rows(&conn), 1);
+
+    // 間に足したり、隣と入れ替えたりしても動かした行だけ
+    todos.insert(1, todo("E"));
+    store.save(&mut todos).unwrap();
+    assert_eq!(updated_rows(&conn), 0);
+    todos.swap(0, 1);
+    store.save(&mut todos).unwrap();
+    assert_eq!(updated_rows(&conn), 1);
+
+    assert_eq!(titles(&Store::new(path).load().unwrap()), vec!["E", "B", "C2", "D"]);
+}
+
+#[test]
+fn saving_keeps_backups_that_restore_can_roll_back_to() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todos.db");
+    let mut store = Store::new(path.clone());
+    store.load().unwrap();
+    let mut todos = vec![todo("A"), todo("B")];
+    store.save(&mut todos).unwrap();
+    todos.remove(0);
+    store.save(&mut todos).unwrap();
+
+    // 空の保存先に書いたときは残すものが無い
+    assert_eq!(list_backups(&path).unwrap().len(), 1);
+    restore_command(&path, Some(1)).unwrap();
+    assert_eq!(titles(&Store::new(path.clone()).load().unwrap()), vec!["A", "B"]);
+    // 戻す前の状態もバックアップに残る
+    assert_eq!(list_backups(&path).unwrap().len(), 2);
+}
+
+#[test]
+fn project_settings_are_saved_with_the_tasks() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todos.db");
+    let mut store = Store::new(path.clone());
+    store.load().unwrap();
+    let projects = ProjectSettings {
+        created: vec!["Garden".to_string()],
+        sort_modes: vec![ProjectSortMode { project: None, mode: SortMode::ByDeadline }],
+    };
+    store.set_projects(projects.clone());
+    store.save(&mut vec![todo("A")]).unwrap();
+
+    let mut reopened = Store::new(path);
+    reopened.load().unwrap();
+    assert_eq!(reopened.projects(), &projects);
+}
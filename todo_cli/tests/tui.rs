@@ -0,0 +1,376 @@
+// 端末を使わずに App を動かし、描画した画面と保存された中身を確かめる
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ltd::app::{App, Effect, Event};
+use ltd::sort::SortMode;
+use ltd::store::Store;
+use ltd::todo::Todo;
+use ltd::ui::draw_ui;
+use std::path::PathBuf;
+use tempfile::TempDir;
+use tui::backend::TestBackend;
+use tui::Terminal;
+
+const WIDTH: u16 = 120;
+const HEIGHT: u16 = 40;
+
+struct Harness {
+    app: App,
+    terminal: Terminal<TestBackend>,
+    path: PathBuf,
+    _dir: TempDir,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("todos.json");
+        Harness {
+            app: App::new(Store::new(path.clone())),
+            terminal: Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap(),
+            path,
+            _dir: dir,
+        }
+    }
+
+    fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Vec<Effect> {
+        let effects = self.app.update(Event::Key(KeyEvent::new(code, modifiers)));
+        // キーごとに描画して、途中の画面でもパニックしないことを確かめる
+        self.terminal.draw(|f| draw_ui(f, &mut self.app)).unwrap();
+        effects
+    }
+
+    // \n は Enter、\x1b は Esc、\x08 は Backspace
+    fn press(&mut self, keys: &str) -> Vec<Effect> {
+        let mut effects = vec![];
+        for c in keys.chars() {
+            let code = match c {
+                '\n' => KeyCode::Enter,
+                '\x1b' => KeyCode::Esc,
+                '\x08' => KeyCode::Backspace,
+                c => KeyCode::Char(c),
+            };
+            effects.extend(self.key(code, KeyModifiers::NONE));
+        }
+        effects
+    }
+
+    fn ctrl(&mut self, c: char) -> Vec<Effect> {
+        self.key(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    // タイトルだけで、あとの項目は既定のまま追加する
+    fn add(&mut self, title: &str) {
+        self.press(&format!("a{}\n\n\n\n\n\n", title));
+    }
+
+    // 画面の文字だけを行ごとに並べる。タイトルのファイルパスは毎回変わるので伏せる
+    fn screen(&mut self) -> String {
+        self.terminal.draw(|f| draw_ui(f, &mut self.app)).unwrap();
+        let buffer = self.terminal.backend().buffer();
+        let mut lines = vec![];
+        for y in 0..buffer.area.height {
+            let line: String = (0..buffer.area.width).map(|x| buffer.get(x, y).symbol.as_str()).collect();
+            lines.push(redact_path(line.trim_end()));
+        }
+        lines.join("\n")
+    }
+
+    fn saved(&self) -> Vec<Todo> {
+        Store::new(self.path.clone()).load().unwrap()
+    }
+
+    fn saved_titles(&self) -> Vec<String> {
+        fn collect(todos: &[Todo], depth: usize, titles: &mut Vec<String>) {
+            for todo in todos {
+                titles.push(format!("{}{}{}", "  ".repeat(depth), if todo.done { "[x] " } else { "" }, todo.title));
+                collect(&todo.subtasks, depth + 1, titles);
+            }
+        }
+        let mut titles = vec![];
+        collect(&self.saved(), 0, &mut titles);
+        titles
+    }
+}
+
+fn redact_path(line: &str) -> String {
+    match line.find(" - /") {
+        Some(start) => {
+            let end = line[start..].find(['─', '┐']).map_or(line.len(), |end| start + end);
+            format!("{} - <file>{}", &line[..start], &line[end..])
+        }
+        None => line.to_string(),
+    }
+}
+
+#[test]
+fn add_shows_the_task_and_saves_it() {
+    let mut h = Harness::new();
+    h.add("Buy milk #errand");
+    h.press("aWrite report\nDraft first\n\n\n\n\n");
+    insta::assert_snapshot!(h.screen());
+
+    let saved = h.saved();
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].title, "Buy milk");
+    assert_eq!(saved[0].tags, vec!["errand"]);
+    assert_eq!(saved[1].content, "Draft first");
+}
+
+#[test]
+fn escape_while_adding_leaves_nothing_behind() {
+    let mut h = Harness::new();
+    h.press("aHalf done\n\x1b");
+    h.add("Next");
+    assert_eq!(h.saved_titles(), vec!["Next"]);
+    assert!(h.app.input_title.is_empty());
+}
+
+#[test]
+fn edit_changes_only_after_the_last_step() {
+    let mut h = Harness::new();
+    h.add("Old title");
+    h.press("e\x08\x08\x08\x08\x08\x08\x08\x08\x08New title\n");
+    assert_eq!(h.saved_titles(), vec!["Old title"]);
+    h.press("Notes\n\n\n\n\n");
+    insta::assert_snapshot!(h.screen());
+
+    let saved = h.saved();
+    assert_eq!(saved[0].title, "New title");
+    assert_eq!(saved[0].content, "Notes");
+}
+
+#[test]
+fn delete_and_undo_restores_the_position() {
+    let mut h = Harness::new();
+    h.add("First");
+    h.add("Second");
+    h.add("Third");
+    h.press("jd");
+    assert_eq!(h.saved_titles(), vec!["First", "Third"]);
+
+    h.press("u");
+    assert_eq!(h.saved_titles(), vec!["First", "Second", "Third"]);
+    insta::assert_snapshot!(h.screen());
+
+    h.ctrl('r');
+    assert_eq!(h.saved_titles(), vec!["First", "Third"]);
+}
+
+#[test]
+fn undo_reverts_edits_toggles_and_subtasks() {
+    let mut h = Harness::new();
+    h.add("Task");
+    h.press("\n");
+    h.press("ASub\n");
+    assert_eq!(h.saved_titles(), vec!["[x] Task", "  Sub"]);
+
+    h.press("u");
+    assert_eq!(h.saved_titles(), vec!["[x] Task"]);
+    h.press("u");
+    assert_eq!(h.saved_titles(), vec!["Task"]);
+    h.press("u");
+    assert_eq!(h.saved_titles(), Vec::<String>::new());
+    h.press("u");
+    assert_eq!(h.app.status_message, "Nothing to undo");
+}
+
+#[test]
+fn subtasks_nest_and_roll_up() {
+    let mut h = Harness::new();
+    h.add("Move house");
+    h.press("APack\n");
+    h.press("jABooks\n");
+    h.press("ADishes\n");
+    h.press("kAChange address\n");
+    // 「Change address」を完了にする
+    h.press("jjjj\n");
+    insta::assert_snapshot!(h.screen());
+
+    assert_eq!(
+        h.saved_titles(),
+        vec!["Move house", "  Pack", "    Books", "    Dishes", "  [x] Change address"]
+    );
+    assert_eq!(h.saved()[0].completion_rate(), 50.0);
+
+    // 畳むと子孫は一覧から消える
+    h.press("kkkkh");
+    assert!(!h.screen().contains("Books"));
+}
+
+#[test]
+fn sort_by_completion_moves_done_tasks_down_and_can_be_undone() {
+    let mut h = Harness::new();
+    h.add("A");
+    h.add("B");
+    h.add("C");
+    h.press("\n");
+    h.press("sss");
+    insta::assert_snapshot!(h.screen());
+
+    h.press("u");
+    let titles: Vec<String> = h.app.todos.iter().map(|todo| todo.title.clone()).collect();
+    assert_eq!(titles, vec!["A", "B", "C"]);
+}
+
+#[test]
+fn search_opens_on_double_space_and_filters() {
+    let mut h = Harness::new();
+    h.add("Buy milk");
+    h.add("Call mom");
+    let effects = h.press("  ");
+    assert_eq!(effects, vec![Effect::HideCursor]);
+    h.press("milk");
+    insta::assert_snapshot!(h.screen());
+
+    let effects = h.press("\x1b");
+    assert_eq!(effects, vec![Effect::ShowCursor]);
+    assert!(h.app.search_query.is_empty());
+}
+
+#[test]
+fn search_cursor_stays_within_results() {
+    let mut h = Harness::new();
+    h.press("  ");
+    h.key(KeyCode::Down, KeyModifiers::NONE);
+    h.key(KeyCode::Up, KeyModifiers::NONE);
+    assert_eq!(h.app.search_state.selected(), Some(0));
+
+    h.press("\x1b");
+    h.add("Buy milk");
+    h.add("Call mom");
+    h.press("  milk");
+    h.key(KeyCode::Down, KeyModifiers::NONE);
+    assert_eq!(h.app.search_state.selected(), Some(0));
+}
+
+#[test]
+fn small_terminal_does_not_panic() {
+    let mut h = Harness::new();
+    h.terminal = Terminal::new(TestBackend::new(40, 12)).unwrap();
+    h.add("Tiny");
+    h.press("  t\x1boq");
+}
+
+#[test]
+fn quit_is_an_effect_not_an_exit() {
+    let mut h = Harness::new();
+    assert_eq!(h.press("q"), vec![Effect::Quit(0)]);
+}
+
+#[test]
+fn search_filter_keeps_the_other_tasks() {
+    let mut h = Harness::new();
+    h.add("Buy milk");
+    h.add("Call mom");
+    h.add("Buy bread");
+    // 2番目の候補(Buy bread)で Enter すると、そのタスクにカーソルが移る
+    h.press("  Buy");
+    h.key(KeyCode::Down, KeyModifiers::NONE);
+    h.press("\n");
+    assert_eq!(h.app.view.search, "Buy");
+    insta::assert_snapshot!(h.screen());
+
+    // 絞り込み中に消しても、ほかのタスクは残る
+    h.press("d");
+    assert_eq!(h.saved_titles(), vec!["Buy milk", "Call mom"]);
+
+    // 絞り込み中の編集は本物のタスクに入る
+    h.press("e\x08\x08\x08\x08\x08\x08\x08\x08Buy eggs\n\n\n\n\n\n");
+    assert_eq!(h.saved_titles(), vec!["Buy eggs", "Call mom"]);
+
+    h.press("\x1b");
+    assert!(h.app.view.search.is_empty());
+    assert!(h.screen().contains("Call mom"));
+}
+
+#[test]
+fn search_popup_highlights_matched_characters() {
+    let mut h = Harness::new();
+    h.add("Write quarterly report");
+    h.press("  wqr");
+    let screen = h.screen();
+    let (y, line) = screen.lines().enumerate().find(|(_, line)| line.contains("✘: Write")).unwrap();
+    let start = line.chars().position(|c| c == 'W').unwrap() as u16;
+    let buffer = h.terminal.backend().buffer();
+    let underlined = |offset: u16| buffer.get(start + offset, y as u16).modifier.contains(tui::style::Modifier::UNDERLINED);
+    assert!(underlined(0) && underlined(6) && underlined(16));
+    assert!(!underlined(1) && !underlined(7));
+}
+
+#[test]
+fn popup_and_filter_use_the_same_matches() {
+    let mut h = Harness::new();
+    h.add("Prepare slides for the quarterly planning meeting");
+    h.add("Call mom");
+    h.press("  plan\n");
+    let rows: Vec<String> = ltd::tree::visible_rows(&h.app.todos, &h.app.view).iter().map(|row| row.todo.title.clone()).collect();
+    assert_eq!(rows, vec!["Prepare slides for the quarterly planning meeting"]);
+}
+
+#[test]
+fn reloading_changes_from_another_ltd_drops_the_undo_history() {
+    let mut h = Harness::new();
+    h.add("Mine");
+    let mut other = Store::new(h.path.clone());
+    let mut todos = other.load().unwrap();
+    todos.insert(0, Todo::new("Theirs".to_string(), String::new(), ltd::priority::Priority::Low, None));
+    other.save(&mut todos).unwrap();
+
+    h.app.update(Event::Tick);
+    // 取り込む前の「Mine の追加」を取り消そうとして Theirs を消さない
+    h.press("u");
+    let titles: Vec<String> = h.saved().into_iter().map(|todo| todo.title).collect();
+    assert_eq!(titles, vec!["Theirs", "Mine"]);
+}
+
+#[test]
+fn undo_after_a_reload_does_not_restore_the_stale_snapshot() {
+    let mut h = Harness::new();
+    h.add("Mine");
+    h.press("\n");
+    let mut other = Store::new(h.path.clone());
+    let mut todos = other.load().unwrap();
+    todos[0].title = "Renamed by another ltd".to_string();
+    other.save(&mut todos).unwrap();
+
+    h.app.update(Event::Tick);
+    // 取り込む前の「完了にする」の記録で、他の ltd の書き換えを戻さない
+    h.press("u");
+    assert_eq!(h.app.status_message, "Nothing to undo");
+    assert_eq!(h.saved_titles(), vec!["[x] Renamed by another ltd"]);
+}
+
+#[test]
+fn folding_and_sorting_do_not_stop_reloading() {
+    let mut h = Harness::new();
+    h.add("Move house");
+    h.press("APack\n");
+    h.press("h");
+    assert!(!h.saved()[0].expanded);
+    h.press("s");
+
+    let mut other = Store::new(h.path.clone());
+    let mut todos = other.load().unwrap();
+    todos.push(Todo::new("Theirs".to_string(), String::new(), ltd::priority::Priority::Low, None));
+    other.save(&mut todos).unwrap();
+
+    h.app.update(Event::Tick);
+    let titles: Vec<&str> = h.app.todos.iter().map(|todo| todo.title.as_str()).collect();
+    assert_eq!(titles, vec!["Move house", "Theirs"]);
+}
+
+#[test]
+fn projects_and_their_sort_modes_are_kept_after_a_restart() {
+    let mut h = Harness::new();
+    h.press("ss");
+    h.press("pnGarden\n");
+    h.press("s");
+
+    // 次に起動した ltd にも、タスクの無いプロジェクトとプロジェクトごとの並び順が残る
+    h.app = App::new(Store::new(h.path.clone()));
+    assert_eq!(h.app.view.created, vec!["Garden"]);
+    assert_eq!(h.app.sort_mode, SortMode::ByPriority);
+    h.press("pjj\n");
+    assert_eq!(h.app.view.project.as_deref(), Some("Garden"));
+    assert_eq!(h.app.sort_mode, SortMode::ByDeadline);
+}